@@ -1,38 +1,207 @@
-use anyhow::{Context, Result};
-use csv;
+use anyhow::{anyhow, Context, Result};
 use serde::{Deserialize, Serialize};
-use std::fs::{File, OpenOptions};
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::fs::{self, File};
 use std::io;
+use std::path::{Path, PathBuf};
 
 use crate::player_rank_lib;
 
-// By default, struct field names are deserialized based on the position of
-// a corresponding field in the CSV data's header record.
-#[derive(Debug, Deserialize)]
-struct ParsedPlayer {
-    name: String,
-    goalie: bool,
-    week1: bool,
-    week2: bool,
+pub fn read_position_config(config_file: &Path) -> Result<player_rank_lib::PositionConfig> {
+    let file = File::open(config_file)?;
+    let config: player_rank_lib::PositionConfig = serde_json::from_reader(io::BufReader::new(file))
+        .with_context(|| {
+            format!(
+                "Failed to read positions from `{}`",
+                config_file.to_string_lossy()
+            )
+        })?;
+
+    if config.positions.is_empty() {
+        return Err(anyhow!("The position config needs at least one position"));
+    }
+    let mut seen = HashSet::new();
+    for info in &config.positions {
+        if !seen.insert(info.position.clone()) {
+            return Err(anyhow!(
+                "Position `{}` is in the config twice",
+                info.position
+            ));
+        }
+    }
+    Ok(config)
+}
+
+// Columns named like week1, week12, or a date like 2024-06-03 say whether players can make a week
+fn is_week_column(column: &str) -> bool {
+    let numbered = column
+        .strip_prefix("week")
+        .is_some_and(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()));
+    let parts: Vec<&str> = column.split('-').collect();
+    let dated = parts.len() == 3
+        && parts
+            .iter()
+            .zip([4, 2, 2])
+            .all(|(part, len)| part.len() == len && part.chars().all(|c| c.is_ascii_digit()));
+    numbered || dated
+}
+
+// Sign-up sheets say yes and no in all sorts of ways
+fn parse_yes_no(value: &str) -> Option<bool> {
+    match value.trim().to_lowercase().as_str() {
+        "true" | "yes" | "y" | "x" | "1" => Some(true),
+        "false" | "no" | "n" | "0" | "" => Some(false),
+        _ => None,
+    }
 }
 
-pub fn parse_player_file(player_file: &std::path::PathBuf) -> Result<player_rank_lib::Players> {
-    let mut players = player_rank_lib::Players::new();
+// Aliases are all in one column, separated by semicolons
+const ALIAS_SEPARATOR: char = ';';
+
+// The player file needs a name column, and can have an id column for ids that don't change when
+// a name does, and an aliases column with names questions might use instead. Positions that need
+// eligibility have a column saying who can play them, named after the position in lower case.
+// Week columns say who can make each week, and anything else is kept as metadata. column_mapping
+// renames columns before any of that, for files with headers of their own, like sign-up sheet
+// exports
+pub fn parse_player_file(
+    player_file: &Path,
+    position_config: &player_rank_lib::PositionConfig,
+    column_mapping: &[(String, String)],
+) -> Result<player_rank_lib::Players> {
+    let mut players = player_rank_lib::Players::with_positions(position_config.clone());
 
     let file = File::open(player_file)?;
     let mut rdr = csv::Reader::from_reader(file);
-    for result in rdr.deserialize() {
-        // Must provide a type hint for automatic deserialization.
-        let player: ParsedPlayer = result?;
+    let headers = rdr.headers()?.clone();
+
+    for (from, _) in column_mapping {
+        if !headers.iter().any(|header| header.trim() == from) {
+            return Err(anyhow!(
+                "The player file has no `{}` column to rename",
+                from
+            ));
+        }
+    }
+    let columns: Vec<String> = headers
+        .iter()
+        .map(|header| {
+            let header = header.trim();
+            match column_mapping.iter().find(|(from, _)| from == header) {
+                Some((_, to)) => to.clone(),
+                None => header.to_string(),
+            }
+        })
+        .collect();
+    let mut seen = HashSet::new();
+    for column in &columns {
+        if !seen.insert(column) {
+            return Err(anyhow!(
+                "The player file has more than one `{}` column",
+                column
+            ));
+        }
+    }
+    let column_index = |name: &str| columns.iter().position(|column| column == name);
+
+    let name_index =
+        column_index("name").ok_or_else(|| anyhow!("The player file needs a `name` column"))?;
+    let id_index = column_index("id");
+    let aliases_index = column_index("aliases");
+    let mut eligibility_columns = Vec::new();
+    for info in &position_config.positions {
+        if !info.needs_eligibility {
+            continue;
+        }
+        let column = info.position.name().to_lowercase();
+        let index = column_index(&column).ok_or_else(|| {
+            anyhow!(
+                "The player file needs a `{}` column saying who can play {}",
+                column,
+                info.position
+            )
+        })?;
+        eligibility_columns.push((info.position.clone(), index));
+    }
+    let week_columns: Vec<usize> = (0..columns.len())
+        .filter(|&i| is_week_column(&columns[i]))
+        .collect();
+    let metadata_columns: Vec<usize> = (0..columns.len())
+        .filter(|&i| {
+            i != name_index
+                && Some(i) != id_index
+                && Some(i) != aliases_index
+                && !week_columns.contains(&i)
+                && !eligibility_columns.iter().any(|(_, index)| *index == i)
+        })
+        .collect();
+    players.weeks = week_columns.iter().map(|&i| columns[i].clone()).collect();
+
+    for result in rdr.records() {
+        let record = result?;
+        let value = |index: usize| record.get(index).unwrap_or_default().trim();
+        let name = value(name_index).to_string();
+        if name.is_empty() {
+            return Err(anyhow!(
+                "There's a player without a name in the player file"
+            ));
+        }
+        let yes_no = |index: usize| {
+            parse_yes_no(value(index)).ok_or_else(|| {
+                anyhow!(
+                    "Expected yes or no in the `{}` column for `{}`, got `{}`",
+                    columns[index],
+                    name,
+                    value(index)
+                )
+            })
+        };
+
+        let mut positions: Vec<player_rank_lib::Position> = position_config
+            .positions
+            .iter()
+            .filter(|info| !info.needs_eligibility)
+            .map(|info| info.position.clone())
+            .collect();
+        for (pos, index) in &eligibility_columns {
+            if yes_no(*index)? {
+                positions.push(pos.clone());
+            }
+        }
+        let availability = week_columns
+            .iter()
+            .map(|&index| yes_no(index))
+            .collect::<Result<Vec<bool>>>()?;
+        let metadata = metadata_columns
+            .iter()
+            .map(|&index| (columns[index].clone(), value(index).to_string()))
+            .collect();
 
         // Add the parsed player to the list of players
+        let id = match id_index.map(value) {
+            Some(id) if !id.is_empty() => id.to_string(),
+            _ => name.clone(),
+        };
+        let aliases = match aliases_index.map(value) {
+            Some(aliases) => aliases
+                .split(ALIAS_SEPARATOR)
+                .map(str::trim)
+                .filter(|alias| !alias.is_empty())
+                .map(String::from)
+                .collect(),
+            None => Vec::new(),
+        };
         players.players.push(player_rank_lib::Player {
-            name: (player.name),
-            goalie: (player.goalie),
-            avail1: (player.week1),
-            avail2: (player.week2),
+            id,
+            name,
+            aliases,
+            positions,
+            availability,
+            metadata,
         });
     }
+    players.check_references()?;
     Ok(players)
 }
 
@@ -44,101 +213,289 @@ struct ParsedQuestion {
     player1_pos: String,
     player2: String,
     player2_pos: String,
-    skill_factor: f64,
+    // Left empty for questions that were skipped
+    skill_factor: Option<f64>,
+    // Older question files don't have these columns
+    #[serde(default)]
+    rater: Option<String>,
+    #[serde(default)]
+    weight: Option<f64>,
 }
 
 // Convert a string to a position enum, handling errors
 fn string_to_position(pos_string: &str) -> Result<player_rank_lib::Position> {
-    match player_rank_lib::Position::from_str(pos_string) {
-        Some(pos) => Ok(pos),
-        None => {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidInput,
-                "Cannot parse questions",
-            ))
-            .with_context(|| format!("Unknown position `{}`", pos_string))
+    match pos_string.parse::<player_rank_lib::Position>() {
+        Ok(pos) => Ok(pos),
+        Err(()) => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Cannot parse questions",
+        ))
+        .with_context(|| format!("Unknown position `{}`", pos_string)),
+    }
+}
+
+/// The line of the question file each question came from, for pointing out problems
+#[derive(Debug, Default)]
+pub struct QuestionLines {
+    pub answered: Vec<u64>,
+    pub skipped: Vec<u64>,
+}
+
+impl QuestionLines {
+    pub fn line(&self, question: player_rank_lib::QuestionRef) -> Option<u64> {
+        match question {
+            player_rank_lib::QuestionRef::Answered(i) => self.answered.get(i).copied(),
+            player_rank_lib::QuestionRef::Skipped(i) => self.skipped.get(i).copied(),
         }
     }
 }
 
-pub fn parse_question_file(
-    question_file: &std::path::PathBuf,
-) -> Result<player_rank_lib::Questions> {
+pub fn parse_question_file(question_file: &Path) -> Result<player_rank_lib::Questions> {
+    Ok(read_question_file(question_file)?.0)
+}
+
+// Read the questions along with the line each one is on
+pub fn read_question_file(
+    question_file: &Path,
+) -> Result<(player_rank_lib::Questions, QuestionLines)> {
     let mut questions = player_rank_lib::Questions::new();
+    let mut lines = QuestionLines::default();
 
     let file = File::open(question_file)?;
     let mut rdr = csv::Reader::from_reader(file);
-    for result in rdr.deserialize() {
+    let headers = rdr.headers()?.clone();
+    for result in rdr.records() {
+        let record = result?;
+        let line = record.position().map_or(0, |position| position.line());
+        let in_file = || format!("On line {} of `{}`", line, question_file.to_string_lossy());
+
         // Must provide a type hint for automatic deserialization.
-        let parsed_question: ParsedQuestion = result?;
+        let parsed_question: ParsedQuestion =
+            record.deserialize(Some(&headers)).with_context(in_file)?;
 
-        // Add the parsed player to the list of players
-        questions.questions.push(player_rank_lib::AnsweredQuestion {
-            question: player_rank_lib::Question {
-                player1: parsed_question.player1,
-                pos1: string_to_position(&parsed_question.player1_pos)?,
-                player2: parsed_question.player2,
-                pos2: string_to_position(&parsed_question.player2_pos)?,
-            },
-            response: parsed_question.skill_factor,
-        });
+        let question = player_rank_lib::Question {
+            player1: parsed_question.player1,
+            pos1: string_to_position(&parsed_question.player1_pos).with_context(in_file)?,
+            player2: parsed_question.player2,
+            pos2: string_to_position(&parsed_question.player2_pos).with_context(in_file)?,
+        };
+
+        // Add the parsed question to the answered or skipped questions
+        match parsed_question.skill_factor {
+            Some(response) => {
+                let mut answered = player_rank_lib::AnsweredQuestion::new(question, response);
+                answered.rater = parsed_question.rater;
+                if let Some(weight) = parsed_question.weight {
+                    answered.weight = weight;
+                }
+                questions.questions.push(answered);
+                lines.answered.push(line);
+            }
+            None => {
+                questions.skipped.push(question);
+                lines.skipped.push(line);
+            }
+        }
+    }
+    Ok((questions, lines))
+}
+
+// How many previous versions of the question file to keep
+const QUESTION_FILE_BACKUPS: usize = 3;
+
+// Add a suffix to the end of a file name, keeping it in the same directory
+fn path_with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut file_name = path.file_name().map(OsString::from).unwrap_or_default();
+    file_name.push(suffix);
+    path.with_file_name(file_name)
+}
+
+// Shift the existing backups of a file back by one, dropping the oldest, and back up the current
+// version of the file as .bak.1
+fn rotate_backups(path: &Path, backups: usize) -> Result<()> {
+    if !path.exists() {
+        return Ok(());
+    }
+    for n in (1..backups).rev() {
+        let older = path_with_suffix(path, &format!(".bak.{}", n));
+        if older.exists() {
+            fs::rename(&older, path_with_suffix(path, &format!(".bak.{}", n + 1)))?;
+        }
     }
-    Ok(questions)
+    fs::copy(path, path_with_suffix(path, ".bak.1"))
+        .with_context(|| format!("Failed to back up `{}`", path.to_string_lossy()))?;
+    Ok(())
+}
+
+// Write to a temporary file next to the destination, then move it over the destination in one
+// step. The destination is created if it doesn't exist, and is never left half written
+fn write_atomically<F>(path: &Path, write: F) -> Result<()>
+where
+    F: FnOnce(&mut File) -> Result<()>,
+{
+    let temp_path = path_with_suffix(path, ".tmp");
+    let result = (|| -> Result<()> {
+        let mut file = File::create(&temp_path)?;
+        write(&mut file)?;
+        file.sync_all()?;
+        fs::rename(&temp_path, path)?;
+        Ok(())
+    })();
+
+    if result.is_err() {
+        // Don't leave the half written file lying around
+        let _ = fs::remove_file(&temp_path);
+    }
+    result.with_context(|| format!("Failed to write `{}`", path.to_string_lossy()))
+}
+
+fn write_csv_atomically<F>(path: &Path, write: F) -> Result<()>
+where
+    F: FnOnce(&mut csv::Writer<&mut File>) -> Result<()>,
+{
+    write_atomically(path, |file| {
+        let mut wtr = csv::Writer::from_writer(file);
+        write(&mut wtr)?;
+        wtr.flush()?;
+        Ok(())
+    })
 }
 
 pub fn write_question_file(
-    question_file: &std::path::PathBuf,
+    question_file: &Path,
     questions: &player_rank_lib::Questions,
 ) -> Result<()> {
-    let file = OpenOptions::new()
-        .write(true)
-        .truncate(false)
-        .open(question_file)?;
-    let mut wtr = csv::Writer::from_writer(file);
+    rotate_backups(question_file, QUESTION_FILE_BACKUPS)?;
+    write_csv_atomically(question_file, |wtr| write_questions(wtr, questions))
+}
 
-    for question in &questions.questions {
+fn write_questions(
+    wtr: &mut csv::Writer<&mut File>,
+    questions: &player_rank_lib::Questions,
+) -> Result<()> {
+    // Answered questions, then skipped questions without a skill factor
+    let answered = questions.questions.iter().map(|answered| {
+        (
+            &answered.question,
+            Some(answered.response),
+            answered.rater.clone(),
+            Some(answered.weight),
+        )
+    });
+    let skipped = questions
+        .skipped
+        .iter()
+        .map(|question| (question, None, None, None));
+    for (question, skill_factor, rater, weight) in answered.chain(skipped) {
         // Convert from a player_rank_lib question struct to one I can serialize
         let parsed_question = ParsedQuestion {
-            player1: question.question.player1.clone(),
-            player1_pos: question.question.pos1.to_str(),
-            player2: question.question.player2.clone(),
-            player2_pos: question.question.pos2.to_str(),
-            skill_factor: question.response.clone(),
+            player1: question.player1.clone(),
+            player1_pos: question.pos1.to_str(),
+            player2: question.player2.clone(),
+            player2_pos: question.pos2.to_str(),
+            skill_factor,
+            rater,
+            weight,
         };
         wtr.serialize(parsed_question)?;
     }
-    wtr.flush()?;
     Ok(())
 }
 
-#[derive(Serialize)]
-struct ParsedRank {
-    pub name: String,
-    pub atk: f64,
-    pub def: f64,
-    pub goalie: Option<f64>,
+// Split a skill's confidence interval into its low and high ends, if there is one
+fn interval_columns(skill: Option<f64>, std_err: Option<f64>) -> (Option<f64>, Option<f64>) {
+    match (skill, std_err) {
+        (Some(skill), Some(std_err)) => {
+            let (low, high) = player_rank_lib::Rank::confidence_interval(skill, std_err);
+            (Some(low), Some(high))
+        }
+        _ => (None, None),
+    }
+}
+
+// An empty column for values we don't have
+fn optional_column(value: Option<f64>) -> String {
+    value.map(|value| value.to_string()).unwrap_or_default()
+}
+
+// Describe which group a player is in for every split position, like "Atk group 2 of 3"
+fn disconnected_warning(
+    name: &str,
+    disconnected: &[player_rank_lib::DisconnectedPosition],
+) -> Option<String> {
+    let groups: Vec<String> = disconnected
+        .iter()
+        .filter_map(|position| {
+            let group = position.group_of(name)?;
+            Some(format!(
+                "{} group {} of {}",
+                position.pos.to_str(),
+                group,
+                position.groups.len()
+            ))
+        })
+        .collect();
+    (!groups.is_empty()).then(|| groups.join("; "))
 }
 
 pub fn write_rank_file(
-    rank_file: &std::path::PathBuf,
+    rank_file: &Path,
     ranks: &player_rank_lib::Ranks,
+    position_config: &player_rank_lib::PositionConfig,
+    disconnected: &[player_rank_lib::DisconnectedPosition],
 ) -> Result<()> {
-    let file = OpenOptions::new()
-        .write(true)
-        .truncate(false)
-        .open(rank_file)?;
-    let mut wtr = csv::Writer::from_writer(file);
+    write_csv_atomically(rank_file, |wtr| {
+        write_ranks(wtr, ranks, position_config, disconnected)
+    })
+}
+
+// One column per position with the skill, named after the position in lower case, then the low
+// and high ends of the 95% confidence interval for each skill
+fn write_ranks(
+    wtr: &mut csv::Writer<&mut File>,
+    ranks: &player_rank_lib::Ranks,
+    position_config: &player_rank_lib::PositionConfig,
+    disconnected: &[player_rank_lib::DisconnectedPosition],
+) -> Result<()> {
+    let positions = position_config.list();
+    let column = |pos: &player_rank_lib::Position| pos.name().to_lowercase();
+
+    let mut header = vec![String::from("name")];
+    header.extend(positions.iter().map(column));
+    for pos in &positions {
+        header.push(format!("{}_low", column(pos)));
+        header.push(format!("{}_high", column(pos)));
+    }
+    header.push(String::from("warning"));
+    wtr.write_record(&header)?;
 
     for rank in &ranks.ranks {
-        // Convert from a player_rank_lib rank struct to one I can serialize
-        let parsed_rank = ParsedRank {
-            name: rank.name.clone(),
-            atk: rank.atk,
-            def: rank.def,
-            goalie: rank.goalie,
-        };
-        wtr.serialize(parsed_rank)?;
+        let mut record = vec![rank.name.clone()];
+        record.extend(positions.iter().map(|pos| optional_column(rank.skill(pos))));
+        for pos in &positions {
+            let (low, high) = interval_columns(rank.skill(pos), rank.std_err(pos));
+            record.push(optional_column(low));
+            record.push(optional_column(high));
+        }
+        record.push(disconnected_warning(&rank.name, disconnected).unwrap_or_default());
+        wtr.write_record(&record)?;
     }
-    wtr.flush()?;
     Ok(())
 }
+
+pub fn read_state_file(state_file: &Path) -> Result<player_rank_lib::PlayerRankState> {
+    let file = File::open(state_file)?;
+    serde_json::from_reader(io::BufReader::new(file)).with_context(|| {
+        format!(
+            "Failed to read state from `{}`",
+            state_file.to_string_lossy()
+        )
+    })
+}
+
+pub fn write_state_file(state_file: &Path, state: &player_rank_lib::PlayerRankState) -> Result<()> {
+    write_atomically(state_file, |file| {
+        serde_json::to_writer_pretty(file, state)?;
+        Ok(())
+    })
+}
@@ -1,32 +1,221 @@
 use anyhow::{Context, Result, anyhow};
-use clap::Parser;
+use clap::{Args, Parser, Subcommand, ValueEnum};
 use log::trace;
-use std::fmt;
 use std::fs;
-use std::io;
+use std::io::{self, BufRead};
 
 mod cli_file_io;
 pub mod player_rank_lib;
 
 // The triple-slash comments can be read by Rust's procedural macros and are used to populate the help message. That's  crazy
 /// This command is used to determine relative player rankings through a series of questions comparing two players' abilities. Provide a list of player's names to begin
-#[derive(Parser)]
+#[derive(Parser, Debug)]
 pub struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Ask comparison questions, then write the answers and the resulting rankings
+    Ask(AskArgs),
+    /// Recompute the rankings from the answers already in a question file
+    Rank(RankArgs),
+    /// Split the players available in a week into balanced teams
+    Teams(TeamsArgs),
+    /// Check that the player and question files can be read and agree with each other
+    Validate(InputFiles),
+    /// Show how many of the possible questions have been answered
+    Stats(InputFiles),
+    /// Combine several raters' question files into one set of answers to rank from
+    Merge(MergeArgs),
+    /// Show how much raters agree with each other, and whose answers stand out
+    Agreement(InputFiles),
+    /// Show how well the answers in each stage link everyone together
+    Connectivity(InputFiles),
+    /// Rewrite the question file to refer to players by their ids, after names in the player file
+    /// change
+    Rename(RenameArgs),
+}
+
+#[derive(Args, Debug)]
+struct InputFiles {
     /// CSV with a list of players and information about them
     player_file: std::path::PathBuf,
     /// CSV with a list of questions with the provided comparisions. May or may not already exist
     question_file: std::path::PathBuf,
-    /// CSV output file with relative rankings for each player
+    /// JSON file listing the positions in the order they're asked, and which ones only some
+    /// players can play [default: Atk, Def, and Goalie for players marked as goalies]
+    #[arg(long)]
+    positions: Option<std::path::PathBuf>,
+    /// Rename a player file column before reading it, like --column "Full Name=name" for files
+    /// exported from a sign-up form. Can be given more than once
+    #[arg(long = "column", value_parser = parse_column_mapping)]
+    column_mapping: Vec<(String, String)>,
+}
+
+#[derive(Args, Debug)]
+struct AskArgs {
+    #[command(flatten)]
+    files: InputFiles,
+    /// CSV output file with relative rankings for each player. Created if it doesn't exist
     output_file: std::path::PathBuf,
+    /// Ask answers that contradict the other answers again before asking new questions
+    #[arg(long)]
+    recheck: bool,
+    /// JSON file the questioning session is saved to, including skipped questions. If it exists,
+    /// the session picks up from it, as long as the player and question files haven't changed
+    /// since it was saved
+    #[arg(long)]
+    state: Option<std::path::PathBuf>,
+    /// Name of the person answering, saved with each answer
+    #[arg(long)]
+    rater: Option<String>,
+    /// How to pick the next question to ask [default: fewest-connections, or the strategy a
+    /// resumed session was saved with]
+    #[arg(long, value_enum)]
+    strategy: Option<Strategy>,
+    /// Method used to turn the answers into rankings
+    #[arg(long, value_enum, default_value_t = Solver::LeastSquares)]
+    solver: Solver,
 }
 
-impl fmt::Debug for Cli {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.debug_struct("Cli")
-            .field("player_file", &self.player_file)
-            .field("question_file", &self.question_file)
-            .field("output_file", &self.output_file)
-            .finish()
+#[derive(Args, Debug)]
+struct RankArgs {
+    #[command(flatten)]
+    files: InputFiles,
+    /// CSV output file with relative rankings for each player. Created if it doesn't exist
+    output_file: std::path::PathBuf,
+    /// Method used to turn the answers into rankings
+    #[arg(long, value_enum, default_value_t = Solver::LeastSquares)]
+    solver: Solver,
+    /// Rank anyway when the answers split the players into groups that can't be compared, with
+    /// each player's group in the warning column
+    #[arg(long)]
+    allow_disconnected: bool,
+}
+
+#[derive(Args, Debug)]
+struct TeamsArgs {
+    #[command(flatten)]
+    files: InputFiles,
+    /// Week to use player availability from, either counting from 1 or by the name of its column
+    #[arg(long, default_value = "1")]
+    week: String,
+    /// Number of teams to make
+    #[arg(long, default_value_t = 2)]
+    teams: usize,
+    /// Method used to turn the answers into rankings
+    #[arg(long, value_enum, default_value_t = Solver::LeastSquares)]
+    solver: Solver,
+    /// Make teams anyway when the answers split the players into groups that can't be compared
+    #[arg(long)]
+    allow_disconnected: bool,
+}
+
+#[derive(Args, Debug)]
+struct MergeArgs {
+    /// CSV the combined questions are written to. Created if it doesn't exist
+    output_file: std::path::PathBuf,
+    /// Question files to combine. Answers without a rater are credited to the file's name
+    #[arg(required = true)]
+    question_files: Vec<std::path::PathBuf>,
+    /// How much a rater's answers count, like --weight alice=2. Can be given more than once.
+    /// Answers from other raters keep the weight they already have
+    #[arg(long = "weight", value_parser = parse_rater_weight)]
+    weights: Vec<(String, f64)>,
+}
+
+#[derive(Args, Debug)]
+struct RenameArgs {
+    #[command(flatten)]
+    files: InputFiles,
+    /// A name the question file uses and the player it means, like --rename Jon=John. Can be
+    /// given more than once. Names and aliases from the player file are picked up without this
+    #[arg(long = "rename", value_parser = parse_rename)]
+    renames: Vec<(String, String)>,
+}
+
+// Parse a player rename given as old=new
+fn parse_rename(arg: &str) -> Result<(String, String), String> {
+    let (old, new) = arg
+        .split_once('=')
+        .ok_or_else(|| format!("Expected old_name=new_name, got `{}`", arg))?;
+    let (old, new) = (old.trim(), new.trim());
+    if old.is_empty() || new.is_empty() {
+        return Err(format!("Expected old_name=new_name, got `{}`", arg));
+    }
+    Ok((old.to_string(), new.to_string()))
+}
+
+// Parse a column rename given as from=to
+fn parse_column_mapping(arg: &str) -> Result<(String, String), String> {
+    let (from, to) = arg
+        .split_once('=')
+        .ok_or_else(|| format!("Expected column=new_name, got `{}`", arg))?;
+    let (from, to) = (from.trim(), to.trim());
+    if from.is_empty() || to.is_empty() {
+        return Err(format!("Expected column=new_name, got `{}`", arg));
+    }
+    Ok((from.to_string(), to.to_string()))
+}
+
+// Parse a rater's weight given as name=weight
+fn parse_rater_weight(arg: &str) -> Result<(String, f64), String> {
+    let (rater, weight) = arg
+        .split_once('=')
+        .ok_or_else(|| format!("Expected rater=weight, got `{}`", arg))?;
+    let weight: f64 = weight
+        .parse()
+        .map_err(|_| format!("Invalid weight `{}`", weight))?;
+    if !weight.is_finite() || weight < 0.0 {
+        return Err(format!("Weight for `{}` can't be negative", rater));
+    }
+    Ok((rater.to_string(), weight))
+}
+
+/// The ranking methods that can be chosen from the command line
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum Solver {
+    /// Least-squares fit of the answers in log space
+    LeastSquares,
+    /// Principal eigenvector of the comparison matrix (analytic hierarchy process)
+    Eigenvector,
+    /// Bradley-Terry model treating answers as fractional wins
+    BradleyTerry,
+}
+
+impl Solver {
+    pub fn solver(&self) -> Box<dyn player_rank_lib::RankingSolver> {
+        match self {
+            Solver::LeastSquares => Box::new(player_rank_lib::LogLeastSquares),
+            Solver::Eigenvector => Box::new(player_rank_lib::Eigenvector),
+            Solver::BradleyTerry => Box::new(player_rank_lib::BradleyTerry),
+        }
+    }
+}
+
+/// The ways of picking questions that can be chosen from the command line
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum Strategy {
+    /// Compare the players with the fewest answers so far
+    FewestConnections,
+    /// Ask whatever would make the ranking most certain
+    MostInformative,
+    /// Ask questions in a random order
+    Random,
+    /// Take turns asking about each player
+    RoundRobin,
+}
+
+impl Strategy {
+    pub fn strategy(&self) -> Box<dyn player_rank_lib::QuestionStrategy> {
+        match self {
+            Strategy::FewestConnections => Box::new(player_rank_lib::FewestConnections),
+            Strategy::MostInformative => Box::new(player_rank_lib::MostInformative),
+            Strategy::Random => Box::new(player_rank_lib::RandomOrder),
+            Strategy::RoundRobin => Box::new(player_rank_lib::RoundRobin::new()),
+        }
     }
 }
 
@@ -44,13 +233,139 @@ fn file_exists(file_path: &std::path::PathBuf) -> Result<()> {
     Ok(())
 }
 
-fn validate_arguments(args: &Cli) -> Result<()> {
+fn validate_arguments(files: &InputFiles) -> Result<()> {
     trace!("Validating arguments");
-    file_exists(&args.player_file)?;
-    file_exists(&args.question_file)?;
-    file_exists(&args.output_file)?;
+    file_exists(&files.player_file)?;
+    // A missing question file just means nothing has been answered yet
+    if files.question_file.exists() {
+        file_exists(&files.question_file)?;
+    }
+    if let Some(position_file) = &files.positions {
+        file_exists(position_file)?;
+    }
     Ok(())
 }
+
+// Check the arguments and read the player file
+fn load_players(files: &InputFiles) -> Result<player_rank_lib::Players> {
+    validate_arguments(files)?;
+    let position_config = match &files.positions {
+        Some(position_file) => cli_file_io::read_position_config(position_file)?,
+        None => player_rank_lib::PositionConfig::default(),
+    };
+    cli_file_io::parse_player_file(&files.player_file, &position_config, &files.column_mapping)
+}
+
+// Point at the questions that mention players who aren't in the player file
+fn warn_unknown_players(unknown: &[player_rank_lib::Error]) {
+    let references: Vec<&str> = unknown
+        .iter()
+        .filter_map(|err| match err {
+            player_rank_lib::Error::UnknownPlayer(reference) => Some(reference.as_str()),
+            _ => None,
+        })
+        .collect();
+    if !references.is_empty() {
+        println!(
+            "Warning: the question file mentions players who aren't in the player file: {}",
+            references.join(", ")
+        );
+    }
+}
+
+// Check and read the player and question files
+fn load_inputs(
+    files: &InputFiles,
+) -> Result<(player_rank_lib::Players, player_rank_lib::Questions)> {
+    let players = load_players(files)?;
+    let (mut questions, lines) = if files.question_file.exists() {
+        cli_file_io::read_question_file(&files.question_file)?
+    } else {
+        Default::default()
+    };
+    // Questions may still use names players have since stopped going by. Anyone who still can't
+    // be found gets pointed out by the checks
+    questions.resolve_players(&players);
+    check_questions(&players, &questions, &lines, &files.question_file)?;
+    Ok((players, questions))
+}
+
+// Explain a problem with a question
+fn describe_problem(
+    players: &player_rank_lib::Players,
+    problem: &player_rank_lib::QuestionProblem,
+    lines: &cli_file_io::QuestionLines,
+) -> String {
+    use player_rank_lib::QuestionProblem;
+    match problem {
+        QuestionProblem::UnknownPosition(pos) => {
+            format!("`{}` isn't a position in the position config", pos)
+        }
+        QuestionProblem::SelfComparison => {
+            String::from("compares a player with themselves in the same position")
+        }
+        QuestionProblem::MixedPositions => {
+            String::from("compares two different players in two different positions")
+        }
+        QuestionProblem::InvalidResponse(response) => {
+            format!("the skill factor {} isn't a positive number", response)
+        }
+        QuestionProblem::InvalidWeight(weight) => {
+            format!("the weight {} can't be negative", weight)
+        }
+        QuestionProblem::UnknownPlayer(reference) => {
+            format!("`{}` isn't in the player file, so it's ignored", reference)
+        }
+        QuestionProblem::CantPlay(player, pos) => format!(
+            "{} can't play {}, so it's ignored",
+            players.display_name(player),
+            pos
+        ),
+        QuestionProblem::Duplicate(first) => match lines.line(*first) {
+            Some(line) => format!("repeats the question on line {}", line),
+            None => String::from("repeats an earlier question"),
+        },
+    }
+}
+
+// Point out any mistakes in the question file, by line. Errors have to be fixed before going on
+fn check_questions(
+    players: &player_rank_lib::Players,
+    questions: &player_rank_lib::Questions,
+    lines: &cli_file_io::QuestionLines,
+    question_file: &std::path::Path,
+) -> Result<()> {
+    let issues = player_rank_lib::validate_questions(players, questions);
+    let mut errors = 0;
+    for issue in &issues {
+        let severity = if issue.problem.is_error() {
+            errors += 1;
+            "Error"
+        } else {
+            "Warning"
+        };
+        let line = match lines.line(issue.question) {
+            Some(line) => format!("line {}", line),
+            None => String::from("unknown line"),
+        };
+        println!(
+            "{}: {:?} {}: {}",
+            severity,
+            question_file,
+            line,
+            describe_problem(players, &issue.problem, lines)
+        );
+    }
+    if errors > 0 {
+        return Err(anyhow!(
+            "{:?} has {} error(s) that need fixing first",
+            question_file,
+            errors
+        ));
+    }
+    Ok(())
+}
+
 enum UserResponse {
     Value(f64),
     Skip,
@@ -58,25 +373,28 @@ enum UserResponse {
     Quit,
 }
 
-fn ask_question(question: &player_rank_lib::Question) {
+fn ask_question(players: &player_rank_lib::Players, question: &player_rank_lib::Question) {
     println!(
         "{} {} vs {} {}",
-        question.player1,
+        players.display_name(&question.player1),
         question.pos1.to_str(),
-        question.player2,
+        players.display_name(&question.player2),
         question.pos2.to_str()
     );
+    println!("  How many times better is the first than the second? (2 = twice as good, 0.5 = half as good) s: skip, n: next section, q: quit");
 }
 
-fn get_response() -> Result<UserResponse> {
-    return Ok(UserResponse::Value(1.0));
+fn get_response<R: BufRead>(input_stream: &mut R) -> Result<UserResponse> {
     loop {
         let mut input = String::new();
 
-        // Read a line from the user
-        io::stdin()
+        // Read a line from the user. Running out of input is the same as quitting
+        let bytes_read = input_stream
             .read_line(&mut input)
-            .with_context(|| format!("Failed to read user input"))?;
+            .context("Failed to read user input")?;
+        if bytes_read == 0 {
+            return Ok(UserResponse::Quit);
+        }
         let input = input.trim();
 
         // Check for specific commands
@@ -91,38 +409,147 @@ fn get_response() -> Result<UserResponse> {
         if let Ok(val) = input.parse::<f64>() {
             return Ok(UserResponse::Value(val));
         };
+
+        println!("Enter a ratio like 1.5, or one of s, n, q");
+    }
+}
+
+// Report answers that contradict the rest of the answers, and return where they are in the
+// answers so they can be asked again
+fn check_consistency(
+    players: &player_rank_lib::Players,
+    questions: &player_rank_lib::Questions,
+    recheck: bool,
+) -> Result<Vec<usize>> {
+    let report = player_rank_lib::find_inconsistencies(players, questions)?;
+    if report.suspects.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    println!("These answers don't agree with the rest:");
+    for suspect in &report.suspects {
+        let answered = &questions.questions[suspect.index];
+        println!(
+            "  {} {} vs {} {}: {} (others suggest about {:.2})",
+            players.display_name(&answered.question.player1),
+            answered.question.pos1.to_str(),
+            players.display_name(&answered.question.player2),
+            answered.question.pos2.to_str(),
+            answered.response,
+            answered.response / suspect.log_residual.exp()
+        );
+    }
+    if !recheck {
+        println!("Run ask with --recheck to answer them again");
+    }
+    Ok(report.suspects.iter().map(|suspect| suspect.index).collect())
+}
+
+// Ask answers found by check_consistency again
+fn recheck_answers<R: BufRead>(
+    player_rank: &mut player_rank_lib::PlayerRank,
+    suspects: &[usize],
+    input_stream: &mut R,
+) -> Result<()> {
+    for &index in suspects {
+        ask_question(
+            player_rank.players(),
+            &player_rank.questions().questions[index].question,
+        );
+        loop {
+            match get_response(input_stream)? {
+                UserResponse::Value(value) => match player_rank.answer_again(index, value) {
+                    Ok(()) => break,
+                    Err(player_rank_lib::Error::InvalidResponse(_)) => println!("Invalid response"),
+                    Err(err) => return Err(err.into()),
+                },
+                // Keep the original answer
+                UserResponse::Skip | UserResponse::NextSection => break,
+                UserResponse::Quit => return Ok(()),
+            }
+        }
+    }
+    Ok(())
+}
+
+// Describe every position whose answers split the players into groups
+fn print_disconnected(disconnected: &[player_rank_lib::DisconnectedPosition]) {
+    for position in disconnected {
+        println!(
+            "Warning: the {} answers split the players into {} groups that can't be compared:",
+            position.pos.to_str(),
+            position.groups.len()
+        );
+        for (i, group) in position.groups.iter().enumerate() {
+            println!("  Group {}: {}", i + 1, group.join(", "));
+        }
+    }
+}
+
+// Find the positions the answers don't link everyone together in. Unless they're allowed, that's
+// an error, since skills in different groups aren't on the same scale
+fn check_connected(
+    players: &player_rank_lib::Players,
+    questions: &player_rank_lib::Questions,
+    allow_disconnected: bool,
+) -> Result<Vec<player_rank_lib::DisconnectedPosition>> {
+    let disconnected = player_rank_lib::disconnected_positions(players, questions);
+    print_disconnected(&disconnected);
+    if !disconnected.is_empty() && !allow_disconnected {
+        let positions: Vec<String> = disconnected
+            .iter()
+            .map(|position| position.pos.to_str())
+            .collect();
+        return Err(anyhow!(
+            "Answers for {} don't compare every player with each other. Answer more questions, or pass --allow-disconnected to rank each group on its own",
+            positions.join(", ")
+        ));
     }
+    Ok(disconnected)
 }
 
-fn run_ranking(player_rank: &mut player_rank_lib::PlayerRank) -> Result<player_rank_lib::Ranks> {
+/// Ask questions from player_rank until the user quits or runs out of questions, reading
+/// responses line by line from input_stream, then rank the players with solver
+pub fn run_ranking<R: BufRead>(
+    player_rank: &mut player_rank_lib::PlayerRank,
+    input_stream: &mut R,
+    solver: &dyn player_rank_lib::RankingSolver,
+) -> Result<player_rank_lib::Ranks> {
+    // A restored session may still be waiting on a response from last time
+    let mut pending_question = player_rank.current_question();
+
     'ranking_loop: loop {
         // Get a question from player_rank
-        let (question, status) = player_rank.get_next_question();
+        let (question, status) = match pending_question.take() {
+            Some(question) => (Some(question), None),
+            None => player_rank.get_next_question(),
+        };
         if let Some(status) = status {
             // Report status to user, or otherwise act on it
             println!("Question status: {:?}", status);
         }
 
         if let Some(question) = question {
-            ask_question(&question);
+            ask_question(player_rank.players(), &question);
 
             // Get a valid response
             let mut get_another_response = true;
             while get_another_response{
                 get_another_response = false; // Assume we won't need to get another response
 
-                let response = get_response()?;
+                let response = get_response(input_stream)?;
                 match response {
                     UserResponse::Value(value) => {
                         if let Err(err) = player_rank.give_response(value){
                             match err{
-                                player_rank_lib::ResponseError::InvalidResponse => {
+                                player_rank_lib::Error::InvalidResponse(_) => {
                                     println!("Invalid response");
                                     get_another_response = true;
                                 }
-                                player_rank_lib::ResponseError::NoActiveQuestion => {
+                                player_rank_lib::Error::NoActiveQuestion => {
                                     return Err(anyhow!("Internal logic error: Gave a response with no active question"));
                                 }
+                                err => return Err(err.into()),
                             }
                         }
                     }
@@ -132,8 +559,9 @@ fn run_ranking(player_rank: &mut player_rank_lib::PlayerRank) -> Result<player_r
                             // If we can't skip sections, get another response
                             get_another_response = true;
                             match  err {
-                                player_rank_lib::NextSectionError::MinSetNotReached => println!("Can't skip sections until the minimum question set has been reached"),
-                                player_rank_lib::NextSectionError::AllQuestionsAsked => println!("All questions have been asked! You gotta quit now"),
+                                player_rank_lib::Error::MinSetNotReached => println!("Can't skip sections until the minimum question set has been reached"),
+                                player_rank_lib::Error::AllQuestionsAsked => println!("All questions have been asked! You gotta quit now"),
+                                err => return Err(err.into()),
                             }
                         }
                     }
@@ -146,33 +574,366 @@ fn run_ranking(player_rank: &mut player_rank_lib::PlayerRank) -> Result<player_r
             break;
         }
     }
-    player_rank.get_ranking()
+    Ok(player_rank.get_ranking_with(solver)?)
 }
 
-pub fn run(args: Cli) -> Result<()> {
-    validate_arguments(&args)?;
-    println!("{:?}", args);
-    // Convert files into their respective structs
-    let players = cli_file_io::parse_player_file(&args.player_file)?;
-    let mut questions = cli_file_io::parse_question_file(&args.question_file)?;
+fn run_ask(args: AskArgs) -> Result<()> {
+    let (players, questions) = load_inputs(&args.files)?;
 
-    // Print parsed players
-    for player in &players.players {
-        println!("{:?}", player);
-    }
-    // Print parsed players
-    for question in &questions.questions {
-        println!("{:?}", question);
+    // Create a PlayerRank object that handles figuring out what questions to ask and creating the ranking
+    let mut player_rank = match &args.state {
+        Some(state_file) if state_file.exists() => {
+            let state = cli_file_io::read_state_file(state_file)?;
+
+            // The files get overwritten with the session's copy at the end, so anything changed in
+            // them since it was saved would be lost
+            if state.players() != &players || state.questions() != &questions {
+                return Err(anyhow!(
+                    "The player or question file has changed since the session was saved in {:?}. \
+                     Delete it to start a new session from the files",
+                    state_file
+                ));
+            }
+            println!("Resuming the session saved in {:?}", state_file);
+            player_rank_lib::PlayerRank::from_state(state)?
+        }
+        _ => {
+            // Print parsed players
+            for player in &players.players {
+                println!("{:?}", player);
+            }
+            // Print parsed players
+            for question in &questions.questions {
+                println!("{:?}", question);
+            }
+
+            player_rank_lib::PlayerRank::new(players, questions, None)?
+        }
+    };
+
+    let suspects = check_consistency(player_rank.players(), player_rank.questions(), args.recheck)?;
+    if args.recheck {
+        recheck_answers(&mut player_rank, &suspects, &mut io::stdin().lock())?;
     }
 
-    // Create a PlayerRank object that handles figuring out what questions to ask and creating the ranking
-    let mut player_rank = player_rank_lib::PlayerRank::new(&players, &mut questions, None);
+    if args.rater.is_some() {
+        player_rank.set_rater(args.rater.clone());
+    }
+    if let Some(strategy) = args.strategy {
+        player_rank.set_strategy(strategy.strategy());
+    }
 
     // Run the routine of asking the user questions and parsing responses
-    let ranks = run_ranking(&mut player_rank)?;
+    let ranks = run_ranking(
+        &mut player_rank,
+        &mut io::stdin().lock(),
+        args.solver.solver().as_ref(),
+    )?;
+
+    // Quitting early can leave players unlinked, which is worth a warning but not losing answers
+    let disconnected =
+        player_rank_lib::disconnected_positions(player_rank.players(), player_rank.questions());
+    print_disconnected(&disconnected);
 
     // Write the outputs back to file
-    cli_file_io::write_question_file(&args.question_file, &questions)?;
-    cli_file_io::write_rank_file(&args.output_file, &ranks)?;
+    cli_file_io::write_question_file(&args.files.question_file, player_rank.questions())?;
+    cli_file_io::write_rank_file(
+        &args.output_file,
+        &ranks,
+        &player_rank.players().position_config,
+        &disconnected,
+    )?;
+    if let Some(state_file) = &args.state {
+        cli_file_io::write_state_file(state_file, &player_rank.save_state())?;
+    }
+    Ok(())
+}
+
+fn run_rank(args: RankArgs) -> Result<()> {
+    let (players, questions) = load_inputs(&args.files)?;
+    let disconnected = check_connected(&players, &questions, args.allow_disconnected)?;
+
+    let ranks = args.solver.solver().rank(&players, &questions)?;
+    cli_file_io::write_rank_file(
+        &args.output_file,
+        &ranks,
+        &players.position_config,
+        &disconnected,
+    )?;
+    println!(
+        "Ranked {} players from {} answers",
+        ranks.ranks.len(),
+        questions.questions.len()
+    );
+    Ok(())
+}
+
+fn run_teams(args: TeamsArgs) -> Result<()> {
+    let (players, questions) = load_inputs(&args.files)?;
+    check_connected(&players, &questions, args.allow_disconnected)?;
+
+    let ranks = args.solver.solver().rank(&players, &questions)?;
+    let week = players.week_number(&args.week).ok_or_else(|| {
+        anyhow!(
+            "There's no week `{}`, the player file has {}",
+            args.week,
+            match players.weeks.len() {
+                0 => String::from("no week columns"),
+                _ => players.weeks.join(", "),
+            }
+        )
+    })?;
+    let teams = player_rank_lib::build_teams(&players, &ranks, week, args.teams)?;
+
+    let positions = players.position_config.list();
+    // Like "atk 3.10, def 2.95, goalie -"
+    let describe = |skill: &dyn Fn(&player_rank_lib::Position) -> Option<f64>| {
+        let skills: Vec<String> = positions
+            .iter()
+            .map(|pos| match skill(pos) {
+                Some(skill) => format!("{} {:.2}", pos.name().to_lowercase(), skill),
+                None => format!("{} -", pos.name().to_lowercase()),
+            })
+            .collect();
+        skills.join(", ")
+    };
+
+    for (i, team) in teams.teams.iter().enumerate() {
+        println!(
+            "Team {}: {}",
+            i + 1,
+            describe(&|pos| team.skills.get(pos).copied())
+        );
+        for pos in &positions {
+            if let Some(specialist) = team.specialists.get(pos) {
+                println!("  {}: {}", pos, specialist);
+            }
+        }
+        for player in &team.players {
+            println!("  {}", player);
+        }
+    }
+    println!(
+        "Difference between strongest and weakest team: {}",
+        describe(&|pos| teams.spreads.get(pos).copied())
+    );
+    Ok(())
+}
+
+fn run_validate(files: InputFiles) -> Result<()> {
+    let (players, questions) = load_inputs(&files)?;
+    println!(
+        "Read {} players, {} answered questions and {} skipped questions",
+        players.players.len(),
+        questions.questions.len(),
+        questions.skipped.len()
+    );
+    if !players.weeks.is_empty() {
+        println!("Availability for: {}", players.weeks.join(", "));
+    }
+    if let Some(player) = players.players.first() {
+        if !player.metadata.is_empty() {
+            let columns: Vec<&str> = player.metadata.keys().map(String::as_str).collect();
+            println!("Other player columns: {}", columns.join(", "));
+        }
+    }
+
+    // Answers that don't agree with the rest are worth a look, but they're still valid
+    check_consistency(&players, &questions, false)?;
+    Ok(())
+}
+
+fn run_stats(files: InputFiles) -> Result<()> {
+    let (players, questions) = load_inputs(&files)?;
+
+    for coverage in player_rank_lib::stage_coverage(&players, &questions) {
+        let percent = if coverage.possible > 0 {
+            100.0 * coverage.answered as f64 / coverage.possible as f64
+        } else {
+            0.0
+        };
+        println!(
+            "{:?}: {} of {} questions answered ({:.0}%)",
+            coverage.stage, coverage.answered, coverage.possible, percent
+        );
+        if !coverage.least_compared.is_empty() {
+            println!(
+                "  Fewest answers ({}): {}",
+                coverage.least_answers,
+                coverage.least_compared.join(", ")
+            );
+        }
+    }
+    Ok(())
+}
+
+fn run_merge(args: MergeArgs) -> Result<()> {
+    let mut merged = player_rank_lib::Questions::new();
+    for question_file in &args.question_files {
+        file_exists(question_file)?;
+        let mut questions = cli_file_io::parse_question_file(question_file)?;
+
+        // Each rater keeps their own question file, so unattributed answers are from its owner
+        let file_rater = question_file
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned());
+        for answered in &mut questions.questions {
+            if answered.rater.is_none() {
+                answered.rater = file_rater.clone();
+            }
+        }
+        println!(
+            "Read {} answered questions from {:?}",
+            questions.questions.len(),
+            question_file
+        );
+        merged.merge(questions);
+    }
+
+    for (rater, weight) in &args.weights {
+        if !merged
+            .questions
+            .iter()
+            .any(|answered| answered.rater.as_ref() == Some(rater))
+        {
+            println!("Warning: no answers from rater `{}`", rater);
+        }
+        merged.set_rater_weight(rater, *weight);
+    }
+
+    cli_file_io::write_question_file(&args.output_file, &merged)?;
+    Ok(())
+}
+
+fn run_agreement(files: InputFiles) -> Result<()> {
+    let (players, questions) = load_inputs(&files)?;
+    let report = player_rank_lib::rater_agreement(&players, &questions)?;
+    if report.raters.is_empty() {
+        println!("None of the answers say who gave them");
+        return Ok(());
+    }
+
+    println!("Questions answered by more than one rater, biggest disagreements first:");
+    for shared in &report.shared_questions {
+        let ratings: Vec<String> = shared
+            .ratings
+            .iter()
+            .map(|(rater, response)| format!("{} {:.2}", rater, response))
+            .collect();
+        println!(
+            "  {} {} vs {} {}: {} (spread {:.2}x)",
+            players.display_name(&shared.question.player1),
+            shared.question.pos1.to_str(),
+            players.display_name(&shared.question.player2),
+            shared.question.pos2.to_str(),
+            ratings.join(", "),
+            shared.spread
+        );
+    }
+
+    println!("Agreement between raters:");
+    for pair in &report.rater_pairs {
+        let correlation = match pair.correlation {
+            Some(correlation) => format!(", correlation {:.2}", correlation),
+            None => String::new(),
+        };
+        println!(
+            "  {} and {}: {} shared questions, typically {:.2}x apart{}",
+            pair.rater1, pair.rater2, pair.shared, pair.typical_ratio, correlation
+        );
+    }
+
+    println!("Distance from the combined ranking, furthest first:");
+    for rater in &report.raters {
+        println!(
+            "  {}: typically {:.2}x off over {} answers",
+            rater.rater,
+            rater.rms_log_residual.exp(),
+            rater.answers
+        );
+    }
+    Ok(())
+}
+
+fn run_connectivity(files: InputFiles) -> Result<()> {
+    let (players, questions) = load_inputs(&files)?;
+
+    for connectivity in player_rank_lib::stage_connectivity(&players, &questions) {
+        println!(
+            "{:?}: {} group(s), {} answer(s) from splitting, algebraic connectivity {:.3}",
+            connectivity.stage,
+            connectivity.components.len(),
+            connectivity.edge_connectivity,
+            connectivity.algebraic_connectivity
+        );
+        if connectivity.components.len() > 1 {
+            for component in &connectivity.components {
+                println!("  Group: {}", component.join(", "));
+            }
+        }
+        if !connectivity.single_answer.is_empty() {
+            println!(
+                "  Linked by a single answer: {}",
+                connectivity.single_answer.join(", ")
+            );
+        }
+    }
     Ok(())
 }
+
+fn run_rename(args: RenameArgs) -> Result<()> {
+    let mut players = load_players(&args.files)?;
+    file_exists(&args.files.question_file)?;
+    let mut questions = cli_file_io::parse_question_file(&args.files.question_file)?;
+
+    // An old name is just another alias for whoever it's renamed to
+    for (old, new) in &args.renames {
+        let id = players
+            .find(new)
+            .map(|player| player.id.clone())
+            .ok_or_else(|| anyhow!("There's no player `{}` to rename `{}` to", new, old))?;
+        let player = players
+            .players
+            .iter_mut()
+            .find(|player| player.id == id)
+            .expect("The player was just found");
+        player.aliases.push(old.clone());
+    }
+    players.check_references()?;
+
+    // Every player reference in the question file, in order
+    let references = |questions: &player_rank_lib::Questions| -> Vec<String> {
+        let answered = questions.questions.iter().map(|answered| &answered.question);
+        answered
+            .chain(&questions.skipped)
+            .flat_map(|question| [question.player1.clone(), question.player2.clone()])
+            .collect()
+    };
+    let before = references(&questions);
+    let unknown = questions.resolve_players(&players);
+    let changed = before
+        .iter()
+        .zip(references(&questions))
+        .filter(|(old, new)| **old != *new)
+        .count();
+
+    cli_file_io::write_question_file(&args.files.question_file, &questions)?;
+    println!("Updated {} player references", changed);
+    warn_unknown_players(&unknown);
+    Ok(())
+}
+
+pub fn run(args: Cli) -> Result<()> {
+    trace!("{:?}", args);
+    match args.command {
+        Command::Ask(args) => run_ask(args),
+        Command::Rank(args) => run_rank(args),
+        Command::Teams(args) => run_teams(args),
+        Command::Validate(files) => run_validate(files),
+        Command::Stats(files) => run_stats(files),
+        Command::Merge(args) => run_merge(args),
+        Command::Agreement(files) => run_agreement(files),
+        Command::Connectivity(files) => run_connectivity(files),
+        Command::Rename(args) => run_rename(args),
+    }
+}
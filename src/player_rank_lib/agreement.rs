@@ -0,0 +1,220 @@
+use crate::player_rank_lib::*;
+use std::collections::{BTreeMap, HashMap};
+
+/// A question answered by more than one rater, with everyone's answers
+#[derive(Debug)]
+pub struct SharedQuestion {
+    pub question: Question,
+    // Each rater's answer, turned around to match the question if they were asked it the other
+    // way. A rater who answered more than once gets the geometric mean of their answers
+    pub ratings: Vec<(String, f64)>,
+    // Largest answer over the smallest. 1 if everyone agrees
+    pub spread: f64,
+}
+
+/// How closely two raters agree on the questions they both answered
+#[derive(Debug)]
+pub struct RaterPairAgreement {
+    pub rater1: String,
+    pub rater2: String,
+    // Number of questions they both answered
+    pub shared: usize,
+    // Geometric mean of how many times bigger one rater's answer is than the other's. 1 if they
+    // always agree
+    pub typical_ratio: f64,
+    // Correlation of their log answers, if they share enough questions to tell
+    pub correlation: Option<f64>,
+}
+
+/// How far a rater's answers are from the ranking everyone's answers make together
+#[derive(Debug)]
+pub struct RaterDeviation {
+    pub rater: String,
+    pub answers: usize,
+    // Root mean square of the log of each answer over what the consensus ranking says it is
+    pub rms_log_residual: f64,
+}
+
+#[derive(Debug)]
+pub struct AgreementReport {
+    // Biggest disagreements first
+    pub shared_questions: Vec<SharedQuestion>,
+    // Least agreement first
+    pub rater_pairs: Vec<RaterPairAgreement>,
+    // Furthest from the consensus first
+    pub raters: Vec<RaterDeviation>,
+}
+
+// Put a question in a consistent order so it matches the same question asked the other way
+// round. Returns the ordered question and whether it had to be turned around
+fn canonical(question: &Question) -> (Question, bool) {
+    let lhs = (question.player1.as_str(), question.pos1.to_str());
+    let rhs = (question.player2.as_str(), question.pos2.to_str());
+    if lhs <= rhs {
+        (question.clone(), false)
+    } else {
+        let reversed = Question {
+            player1: question.player2.clone(),
+            pos1: question.pos2.clone(),
+            player2: question.player1.clone(),
+            pos2: question.pos1.clone(),
+        };
+        (reversed, true)
+    }
+}
+
+// Pearson correlation of paired values, if there are enough of them and they vary
+fn correlation(pairs: &[(f64, f64)]) -> Option<f64> {
+    if pairs.len() < 3 {
+        return None;
+    }
+    let n = pairs.len() as f64;
+    let mean_a = pairs.iter().map(|(a, _)| a).sum::<f64>() / n;
+    let mean_b = pairs.iter().map(|(_, b)| b).sum::<f64>() / n;
+    let mut covariance = 0.0;
+    let mut variance_a = 0.0;
+    let mut variance_b = 0.0;
+    for (a, b) in pairs {
+        covariance += (a - mean_a) * (b - mean_b);
+        variance_a += (a - mean_a).powi(2);
+        variance_b += (b - mean_b).powi(2);
+    }
+    if variance_a < 1e-12 || variance_b < 1e-12 {
+        return None;
+    }
+    Some(covariance / (variance_a * variance_b).sqrt())
+}
+
+/// Compare the answers of every rater with each other and with the ranking all their answers make
+/// together. Answers without a rater aren't anyone's, so they only count towards the consensus
+pub fn rater_agreement(players: &Players, questions: &Questions) -> Result<AgreementReport> {
+    // Each rater's log answers to every question, in a consistent orientation
+    let mut by_question: HashMap<Question, BTreeMap<&str, Vec<f64>>> = HashMap::new();
+    for answered in &questions.questions {
+        let rater = match &answered.rater {
+            Some(rater) => rater.as_str(),
+            None => continue,
+        };
+        if !answered.response.is_finite() || answered.response <= 0.0 {
+            continue;
+        }
+        let (question, reversed) = canonical(&answered.question);
+        let log_response = answered.response.ln();
+        by_question
+            .entry(question)
+            .or_default()
+            .entry(rater)
+            .or_default()
+            .push(if reversed {
+                -log_response
+            } else {
+                log_response
+            });
+    }
+
+    // Combine repeated answers from the same rater with a geometric mean
+    let by_question: Vec<(Question, BTreeMap<&str, f64>)> = by_question
+        .into_iter()
+        .map(|(question, ratings)| {
+            let ratings = ratings
+                .into_iter()
+                .map(|(rater, logs)| (rater, logs.iter().sum::<f64>() / logs.len() as f64))
+                .collect();
+            (question, ratings)
+        })
+        .collect();
+
+    let mut shared_questions: Vec<SharedQuestion> = by_question
+        .iter()
+        .filter(|(_, ratings)| ratings.len() > 1)
+        .map(|(question, ratings)| {
+            let max = ratings.values().cloned().fold(f64::NEG_INFINITY, f64::max);
+            let min = ratings.values().cloned().fold(f64::INFINITY, f64::min);
+            SharedQuestion {
+                question: question.clone(),
+                ratings: ratings
+                    .iter()
+                    .map(|(rater, log)| (rater.to_string(), log.exp()))
+                    .collect(),
+                spread: (max - min).exp(),
+            }
+        })
+        .collect();
+    shared_questions.sort_by(|a, b| b.spread.total_cmp(&a.spread));
+
+    // Compare every pair of raters on the questions they both answered
+    let mut raters: Vec<&str> = by_question
+        .iter()
+        .flat_map(|(_, ratings)| ratings.keys().cloned())
+        .collect();
+    raters.sort();
+    raters.dedup();
+    let mut rater_pairs = Vec::new();
+    for (i, rater1) in raters.iter().enumerate() {
+        for rater2 in &raters[i + 1..] {
+            let pairs: Vec<(f64, f64)> = by_question
+                .iter()
+                .filter_map(|(_, ratings)| Some((*ratings.get(rater1)?, *ratings.get(rater2)?)))
+                .collect();
+            if pairs.is_empty() {
+                continue;
+            }
+            let mean_difference =
+                pairs.iter().map(|(a, b)| (a - b).abs()).sum::<f64>() / pairs.len() as f64;
+            rater_pairs.push(RaterPairAgreement {
+                rater1: rater1.to_string(),
+                rater2: rater2.to_string(),
+                shared: pairs.len(),
+                typical_ratio: mean_difference.exp(),
+                correlation: correlation(&pairs),
+            });
+        }
+    }
+    rater_pairs.sort_by(|a, b| b.typical_ratio.total_cmp(&a.typical_ratio));
+
+    // See how far each rater is from the ranking everyone's answers make together
+    let ranks = LogLeastSquares.rank(players, questions)?;
+    let rank_of: HashMap<&str, &Rank> = ranks
+        .ranks
+        .iter()
+        .map(|rank| (rank.id.as_str(), rank))
+        .collect();
+    let mut residuals: BTreeMap<&str, (f64, usize)> = BTreeMap::new();
+    for answered in &questions.questions {
+        let question = &answered.question;
+        let rater = match &answered.rater {
+            Some(rater) => rater.as_str(),
+            None => continue,
+        };
+        if !answered.response.is_finite() || answered.response <= 0.0 {
+            continue;
+        }
+        let skill = |player: &str, pos| rank_of.get(player).and_then(|rank| rank.skill(pos));
+        let (skill1, skill2) = match (
+            skill(&question.player1, &question.pos1),
+            skill(&question.player2, &question.pos2),
+        ) {
+            (Some(skill1), Some(skill2)) => (skill1, skill2),
+            _ => continue,
+        };
+        let residual = (answered.response / (skill1 / skill2)).ln();
+        let total = residuals.entry(rater).or_insert((0.0, 0));
+        total.0 += residual.powi(2);
+        total.1 += 1;
+    }
+    let mut raters: Vec<RaterDeviation> = residuals
+        .into_iter()
+        .map(|(rater, (sum_sq, answers))| RaterDeviation {
+            rater: rater.to_string(),
+            answers,
+            rms_log_residual: (sum_sq / answers as f64).sqrt(),
+        })
+        .collect();
+    raters.sort_by(|a, b| b.rms_log_residual.total_cmp(&a.rms_log_residual));
+
+    Ok(AgreementReport {
+        shared_questions,
+        rater_pairs,
+        raters,
+    })
+}
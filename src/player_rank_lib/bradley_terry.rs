@@ -0,0 +1,67 @@
+use crate::player_rank_lib::solver::{self, Comparison, PositionSkills};
+use crate::player_rank_lib::*;
+
+const MAX_ITERATIONS: usize = 10000;
+const TOLERANCE: f64 = 1e-12;
+
+/// Rank players with a Bradley-Terry model, where a player with skill a beats a player with
+/// skill b with probability a / (a + b). An answer of r counts as r / (1 + r) of a win for the
+/// first player and the rest of a win for the second, scaled by the answer's weight, and the
+/// skills are fit with Hunter's minorization-maximization algorithm
+pub struct BradleyTerry;
+
+fn solve_position(num_players: usize, comparisons: &[Comparison]) -> Result<PositionSkills> {
+    // Share out the wins
+    let mut wins = vec![0.0; num_players];
+    for comparison in comparisons {
+        let ratio = comparison.log_ratio.exp();
+        wins[comparison.lhs] += comparison.weight * ratio / (1.0 + ratio);
+        wins[comparison.rhs] += comparison.weight / (1.0 + ratio);
+    }
+
+    let component = solver::connected_components(num_players, comparisons);
+    let mut skills = vec![1.0; num_players];
+    for _ in 0..MAX_ITERATIONS {
+        let mut denominators = vec![0.0; num_players];
+        for comparison in comparisons {
+            let games = comparison.weight / (skills[comparison.lhs] + skills[comparison.rhs]);
+            denominators[comparison.lhs] += games;
+            denominators[comparison.rhs] += games;
+        }
+
+        // Players without any comparisons keep a skill of 1
+        let mut log_skills: Vec<f64> = (0..num_players)
+            .map(|i| {
+                if denominators[i] > 0.0 {
+                    (wins[i] / denominators[i]).ln()
+                } else {
+                    0.0
+                }
+            })
+            .collect();
+        solver::center_components(&mut log_skills, &component);
+
+        let next: Vec<f64> = log_skills.iter().map(|s| s.exp()).collect();
+        let change = next
+            .iter()
+            .zip(&skills)
+            .map(|(a, b)| (a - b).abs())
+            .fold(0.0, f64::max);
+        skills = next;
+        if change < TOLERANCE {
+            break;
+        }
+    }
+
+    Ok(PositionSkills {
+        log_skills: skills.iter().map(|s| s.ln()).collect(),
+        std_errs: vec![None; num_players],
+        degree: solver::comparison_degrees(num_players, comparisons),
+    })
+}
+
+impl RankingSolver for BradleyTerry {
+    fn rank(&self, players: &Players, questions: &Questions) -> Result<Ranks> {
+        solver::rank_by_position(players, questions, solve_position)
+    }
+}
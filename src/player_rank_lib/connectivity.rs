@@ -0,0 +1,296 @@
+use crate::player_rank_lib::solver::{self, Comparison};
+use crate::player_rank_lib::*;
+
+const MAX_SWEEPS: usize = 100;
+const TOLERANCE: f64 = 1e-12;
+const ELIGIBLE: &str = "Only players who can play a position are compared in it";
+
+/// How well the answers in a stage tie everyone together. Position stages link players, and the
+/// self rating stage links positions
+#[derive(Debug)]
+pub struct StageConnectivity {
+    pub stage: Stage,
+    // Groups linked to each other by answers, largest first. Anyone in a different group can't be
+    // compared
+    pub components: Vec<Vec<String>>,
+    // The fewest answers that would have to be removed to split everyone up. 0 if they're split
+    // already
+    pub edge_connectivity: usize,
+    // Those linked to everyone else by a single answer
+    pub single_answer: Vec<String>,
+    // Second smallest eigenvalue of the laplacian of the answers. 0 if everyone is split up, and
+    // bigger the better linked everyone is
+    pub algebraic_connectivity: f64,
+}
+
+// The fewest edges whose removal splits the graph, with the Stoer-Wagner algorithm. edges[i][j]
+// is the number of edges between i and j
+fn min_cut(mut edges: Vec<Vec<f64>>) -> f64 {
+    let n = edges.len();
+    if n < 2 {
+        return 0.0;
+    }
+    // Vertices that haven't been merged into another one yet
+    let mut active: Vec<usize> = (0..n).collect();
+    let mut best = f64::INFINITY;
+    while active.len() > 1 {
+        // Grow a set from the first vertex, always adding the most tightly connected vertex
+        let mut weights: Vec<f64> = vec![0.0; n];
+        let mut added = vec![false; n];
+        let (mut previous, mut last) = (active[0], active[0]);
+        for _ in 0..active.len() {
+            let next = *active
+                .iter()
+                .filter(|&&v| !added[v])
+                .max_by(|&&a, &&b| weights[a].total_cmp(&weights[b]))
+                .expect("Some vertex hasn't been added");
+            added[next] = true;
+            previous = last;
+            last = next;
+            for &v in &active {
+                weights[v] += edges[next][v];
+            }
+        }
+
+        // Cutting off the last vertex added is a candidate, then merge it into the one before
+        best = f64::min(best, weights[last]);
+        let merged: Vec<f64> = edges[previous]
+            .iter()
+            .zip(&edges[last])
+            .map(|(a, b)| a + b)
+            .collect();
+        for (row, weight) in edges.iter_mut().zip(&merged) {
+            row[previous] = *weight;
+        }
+        edges[previous] = merged;
+        edges[previous][previous] = 0.0;
+        active.retain(|&v| v != last);
+    }
+    best
+}
+
+// Eigenvalues of a symmetric matrix, smallest first, with the cyclic Jacobi method
+fn symmetric_eigenvalues(mut a: Vec<Vec<f64>>) -> Vec<f64> {
+    let n = a.len();
+    for _ in 0..MAX_SWEEPS {
+        let off_diagonal: f64 = (0..n)
+            .flat_map(|i| (0..n).filter(move |&j| j != i).map(move |j| (i, j)))
+            .map(|(i, j)| a[i][j].powi(2))
+            .sum();
+        if off_diagonal < TOLERANCE {
+            break;
+        }
+        for p in 0..n {
+            for q in (p + 1)..n {
+                if a[p][q].abs() < TOLERANCE {
+                    continue;
+                }
+                // Rotate rows and columns p and q to zero out a[p][q]
+                let theta = (a[q][q] - a[p][p]) / (2.0 * a[p][q]);
+                let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+                let c = 1.0 / (t * t + 1.0).sqrt();
+                let s = t * c;
+                for row in a.iter_mut() {
+                    let (akp, akq) = (row[p], row[q]);
+                    row[p] = c * akp - s * akq;
+                    row[q] = s * akp + c * akq;
+                }
+                let (row_p, row_q) = (a[p].clone(), a[q].clone());
+                let rotated = row_p.iter().zip(&row_q);
+                a[p] = rotated
+                    .clone()
+                    .map(|(apk, aqk)| c * apk - s * aqk)
+                    .collect();
+                a[q] = rotated.map(|(apk, aqk)| s * apk + c * aqk).collect();
+            }
+        }
+    }
+    let mut eigenvalues: Vec<f64> = (0..n).map(|i| a[i][i]).collect();
+    eigenvalues.sort_by(|a, b| a.total_cmp(b));
+    eigenvalues
+}
+
+// Work out the connectivity of a graph between named vertices
+fn graph_connectivity(
+    stage: Stage,
+    names: &[String],
+    comparisons: &[Comparison],
+) -> StageConnectivity {
+    let n = names.len();
+    let mut edges = vec![vec![0.0; n]; n];
+    for comparison in comparisons {
+        edges[comparison.lhs][comparison.rhs] += 1.0;
+        edges[comparison.rhs][comparison.lhs] += 1.0;
+    }
+
+    let component = solver::connected_components(n, comparisons);
+    let num_components = component.iter().max().map_or(0, |c| c + 1);
+    let mut components: Vec<Vec<String>> = vec![Vec::new(); num_components];
+    for (name, c) in names.iter().zip(&component) {
+        components[*c].push(name.clone());
+    }
+    components.sort_by_key(|members| std::cmp::Reverse(members.len()));
+
+    let degree = solver::comparison_degrees(n, comparisons);
+    let single_answer = names
+        .iter()
+        .zip(&degree)
+        .filter(|(_, degree)| **degree == 1)
+        .map(|(name, _)| name.clone())
+        .collect();
+
+    let laplacian: Vec<Vec<f64>> = (0..n)
+        .map(|i| {
+            (0..n)
+                .map(|j| {
+                    if i == j {
+                        degree[i] as f64
+                    } else {
+                        -edges[i][j]
+                    }
+                })
+                .collect()
+        })
+        .collect();
+    let algebraic_connectivity = if n < 2 || num_components > 1 {
+        0.0
+    } else {
+        symmetric_eigenvalues(laplacian)[1].max(0.0)
+    };
+
+    let edge_connectivity = if num_components > 1 {
+        0
+    } else {
+        min_cut(edges).round() as usize
+    };
+
+    StageConnectivity {
+        stage,
+        components,
+        edge_connectivity,
+        single_answer,
+        algebraic_connectivity,
+    }
+}
+
+// Self ratings as comparisons between positions, referencing positions by their index
+fn self_rating_comparisons(
+    players: &Players,
+    questions: &Questions,
+    positions: &[Position],
+) -> Vec<Comparison> {
+    let pos_index = |pos: &Position| positions.iter().position(|p| p == pos);
+
+    let mut comparisons = Vec::new();
+    for (index, answered) in questions.questions.iter().enumerate() {
+        let question = &answered.question;
+        if Stage::of_question(question) != Some(Stage::SelfRating) {
+            continue;
+        }
+        if !answered.response.is_finite() || answered.response <= 0.0 || answered.weight <= 0.0 {
+            continue;
+        }
+        let player = match players.players.iter().find(|p| p.id == question.player1) {
+            Some(player) => player,
+            None => continue,
+        };
+        let (lhs, rhs) = match (pos_index(&question.pos1), pos_index(&question.pos2)) {
+            (Some(lhs), Some(rhs)) => (lhs, rhs),
+            _ => continue,
+        };
+        if !player.can_play(&question.pos1) || !player.can_play(&question.pos2) {
+            continue;
+        }
+        comparisons.push(Comparison {
+            lhs,
+            rhs,
+            log_ratio: answered.response.ln(),
+            weight: answered.weight,
+            index,
+        });
+    }
+    comparisons
+}
+
+/// Measure how well the answers in every stage link everyone together
+pub fn stage_connectivity(players: &Players, questions: &Questions) -> Vec<StageConnectivity> {
+    let mut report = Vec::new();
+
+    let mut stage = Stage::first(&players.position_config);
+    while stage != Stage::Done {
+        let connectivity = match &stage {
+            Stage::Position(pos) => {
+                // Only players who can play the position are part of its graph
+                let eligible: Vec<usize> = (0..players.players.len())
+                    .filter(|&i| players.players[i].can_play(pos))
+                    .collect();
+                let names: Vec<String> = eligible
+                    .iter()
+                    .map(|&i| players.players[i].name.clone())
+                    .collect();
+                let comparisons: Vec<Comparison> =
+                    solver::position_comparisons(players, questions, pos)
+                        .into_iter()
+                        .map(|comparison| Comparison {
+                            lhs: eligible.binary_search(&comparison.lhs).expect(ELIGIBLE),
+                            rhs: eligible.binary_search(&comparison.rhs).expect(ELIGIBLE),
+                            ..comparison
+                        })
+                        .collect();
+                graph_connectivity(stage.clone(), &names, &comparisons)
+            }
+            _ => {
+                // Only positions somebody can play are part of the self rating graph
+                let positions: Vec<Position> = players
+                    .position_config
+                    .list()
+                    .into_iter()
+                    .filter(|pos| players.players.iter().any(|p| p.can_play(pos)))
+                    .collect();
+                let names: Vec<String> = positions.iter().map(|pos| pos.to_str()).collect();
+                let comparisons = self_rating_comparisons(players, questions, &positions);
+                graph_connectivity(stage.clone(), &names, &comparisons)
+            }
+        };
+        report.push(connectivity);
+        stage = stage.next(&players.position_config);
+    }
+    report
+}
+
+/// A position whose answers split the players into groups that haven't been compared with each
+/// other. Skills are only comparable within a group, since each group is scaled on its own
+#[derive(Debug)]
+pub struct DisconnectedPosition {
+    pub pos: Position,
+    // Players in each group, largest group first
+    pub groups: Vec<Vec<String>>,
+}
+
+impl DisconnectedPosition {
+    // The group a player is in, counting from 1, if they can play the position
+    pub fn group_of(&self, player: &str) -> Option<usize> {
+        self.groups
+            .iter()
+            .position(|group| group.iter().any(|name| name == player))
+            .map(|i| i + 1)
+    }
+}
+
+/// Find the positions where the answers don't link every player who can play them together
+pub fn disconnected_positions(
+    players: &Players,
+    questions: &Questions,
+) -> Vec<DisconnectedPosition> {
+    stage_connectivity(players, questions)
+        .into_iter()
+        .filter(|connectivity| connectivity.components.len() > 1)
+        .filter_map(|connectivity| match connectivity.stage {
+            Stage::Position(pos) => Some(DisconnectedPosition {
+                pos,
+                groups: connectivity.components,
+            }),
+            _ => None,
+        })
+        .collect()
+}
@@ -0,0 +1,197 @@
+use crate::player_rank_lib::solver::{self, Comparison, PositionSolution};
+use crate::player_rank_lib::*;
+use std::collections::{HashSet, VecDeque};
+
+// How many standard deviations off an answer has to be before we call it suspicious
+const SUSPECT_SCORE: f64 = 2.5;
+// How many of the worst cycles to report per stage
+const MAX_CYCLES: usize = 5;
+
+/// An answer that disagrees with what the rest of the answers say it should be
+#[derive(Debug)]
+pub struct SuspectAnswer {
+    // Index into the question list
+    pub index: usize,
+    // Log of the answer over what the ranking says it should be
+    pub log_residual: f64,
+    // How many standard deviations off the answer is, judged by all the other answers
+    pub score: f64,
+}
+
+/// A loop of answers whose ratios should multiply to 1, but don't
+#[derive(Debug)]
+pub struct InconsistentCycle {
+    pub stage: Stage,
+    // Indices into the question list, in the order they go around the loop
+    pub answers: Vec<usize>,
+    // Log of the product of the ratios around the loop. 0 if the answers agree perfectly
+    pub log_error: f64,
+}
+
+#[derive(Debug)]
+pub struct ConsistencyReport {
+    // Worst cycles first
+    pub cycles: Vec<InconsistentCycle>,
+    // Most suspicious answers first
+    pub suspects: Vec<SuspectAnswer>,
+}
+
+// Find the shortest loop through a comparison, going back from its rhs to its lhs through other
+// comparisons. Returns the comparisons in the loop and the log error around it
+fn shortest_cycle(
+    num_players: usize,
+    comparisons: &[Comparison],
+    through: usize,
+) -> Option<(Vec<usize>, f64)> {
+    let start = comparisons[through].rhs;
+    let goal = comparisons[through].lhs;
+
+    let mut neighbours: Vec<Vec<usize>> = vec![Vec::new(); num_players];
+    for (i, comparison) in comparisons.iter().enumerate() {
+        if i != through {
+            neighbours[comparison.lhs].push(i);
+            neighbours[comparison.rhs].push(i);
+        }
+    }
+
+    // Breadth first search, remembering which comparison we arrived at each player through
+    let mut arrived_by: Vec<Option<usize>> = vec![None; num_players];
+    let mut visited = vec![false; num_players];
+    let mut queue = VecDeque::from([start]);
+    visited[start] = true;
+    while let Some(player) = queue.pop_front() {
+        if player == goal {
+            break;
+        }
+        for &i in &neighbours[player] {
+            let comparison = &comparisons[i];
+            let next = if comparison.lhs == player {
+                comparison.rhs
+            } else {
+                comparison.lhs
+            };
+            if !visited[next] {
+                visited[next] = true;
+                arrived_by[next] = Some(i);
+                queue.push_back(next);
+            }
+        }
+    }
+    if !visited[goal] {
+        return None;
+    }
+
+    // Walk the loop: lhs to rhs through the comparison, then back from rhs to lhs along the path.
+    // Going from a to b adds log(a / b), so the total comes out to 0 for consistent answers
+    let mut cycle = vec![through];
+    let mut log_error = comparisons[through].log_ratio;
+    let mut path = Vec::new();
+    let mut player = goal;
+    while player != start {
+        let i = arrived_by[player]?;
+        let comparison = &comparisons[i];
+        // We arrived at player from the other end, so the loop goes other -> player
+        let (other, log_ratio) = if comparison.lhs == player {
+            (comparison.rhs, -comparison.log_ratio)
+        } else {
+            (comparison.lhs, comparison.log_ratio)
+        };
+        path.push((i, log_ratio));
+        player = other;
+    }
+    for (i, log_ratio) in path.into_iter().rev() {
+        cycle.push(i);
+        log_error += log_ratio;
+    }
+    Some((cycle, log_error))
+}
+
+// Score every comparison on how well the other answers agree with it. Comparisons that are the
+// only link between two groups of players can't be checked, and get no score
+fn score_comparisons(solution: &PositionSolution, comparisons: &[Comparison]) -> Vec<Option<f64>> {
+    comparisons
+        .iter()
+        .map(|comparison| {
+            // How much of the comparison's answer is explained by the answer itself
+            let leverage = solution.leverage(comparison);
+            if 1.0 - leverage < 1e-9 {
+                return None;
+            }
+
+            // Judge the answer against the variance of all the other answers. Answers with more
+            // weight are expected to be closer
+            let residual = solution.residual(comparison);
+            let weighted_sq = comparison.weight * residual.powi(2);
+            let variance = solution.answer_variance(weighted_sq / (1.0 - leverage), 1.0);
+            Some(residual.abs() / (variance / comparison.weight * (1.0 - leverage)).sqrt())
+        })
+        .collect()
+}
+
+/// Look for answers in each position stage that contradict each other, like A = 1.5 B,
+/// B = 1.5 C and C = 1.5 A
+pub fn find_inconsistencies(players: &Players, questions: &Questions) -> Result<ConsistencyReport> {
+    let num_players = players.players.len();
+    let mut report = ConsistencyReport {
+        cycles: Vec::new(),
+        suspects: Vec::new(),
+    };
+
+    for pos in players.position_config.list() {
+        let comparisons = solver::position_comparisons(players, questions, &pos);
+        let solution = solver::solve_comparisons(num_players, &comparisons)?;
+        let scores = score_comparisons(&solution, &comparisons);
+
+        for (comparison, score) in comparisons.iter().zip(&scores) {
+            if let Some(score) = score {
+                if *score > SUSPECT_SCORE {
+                    report.suspects.push(SuspectAnswer {
+                        index: comparison.index,
+                        log_residual: solution.residual(comparison),
+                        score: *score,
+                    });
+                }
+            }
+        }
+
+        // Look for loops through the answers that fit worst
+        let mut by_residual: Vec<usize> = (0..comparisons.len())
+            .filter(|&i| scores[i].is_some())
+            .collect();
+        by_residual.sort_by(|&a, &b| {
+            let residual_a = solution.residual(&comparisons[a]).abs();
+            let residual_b = solution.residual(&comparisons[b]).abs();
+            residual_b.total_cmp(&residual_a)
+        });
+
+        let mut stage_cycles = Vec::new();
+        let mut seen_cycles = HashSet::new();
+        for i in by_residual {
+            if stage_cycles.len() >= MAX_CYCLES {
+                break;
+            }
+            let (cycle, log_error) = match shortest_cycle(num_players, &comparisons, i) {
+                Some(cycle) => cycle,
+                None => continue,
+            };
+            // Ignore loops that agree, and loops we've found through another answer
+            let mut key: Vec<usize> = cycle.clone();
+            key.sort();
+            if log_error.abs() < 1e-9 || !seen_cycles.insert(key) {
+                continue;
+            }
+            stage_cycles.push(InconsistentCycle {
+                stage: Stage::Position(pos.clone()),
+                answers: cycle.iter().map(|&c| comparisons[c].index).collect(),
+                log_error,
+            });
+        }
+        report.cycles.extend(stage_cycles);
+    }
+
+    report
+        .cycles
+        .sort_by(|a, b| b.log_error.abs().total_cmp(&a.log_error.abs()));
+    report.suspects.sort_by(|a, b| b.score.total_cmp(&a.score));
+    Ok(report)
+}
@@ -0,0 +1,95 @@
+use crate::player_rank_lib::*;
+use std::collections::{HashMap, HashSet};
+
+/// How many of a stage's possible questions have been answered
+#[derive(Debug)]
+pub struct StageCoverage {
+    pub stage: Stage,
+    // Distinct questions answered. A question asked both ways round only counts once
+    pub answered: usize,
+    pub possible: usize,
+    // The players with the fewest answers in this stage, and how many answers that is
+    pub least_compared: Vec<String>,
+    pub least_answers: usize,
+}
+
+// Number of distinct questions that could be asked in a stage
+fn possible_questions(players: &Players, stage: &Stage) -> usize {
+    match stage {
+        Stage::Position(pos) => {
+            let eligible = players.players.iter().filter(|p| p.can_play(pos)).count();
+            eligible * eligible.saturating_sub(1) / 2
+        }
+        Stage::SelfRating => players
+            .players
+            .iter()
+            .map(|player| {
+                let positions = players
+                    .position_config
+                    .list()
+                    .into_iter()
+                    .filter(|pos| player.can_play(pos))
+                    .count();
+                positions * positions.saturating_sub(1) / 2
+            })
+            .sum(),
+        Stage::Done => 0,
+    }
+}
+
+/// Count the answered questions in every stage, and find who's been compared the least
+pub fn stage_coverage(players: &Players, questions: &Questions) -> Vec<StageCoverage> {
+    let mut coverage = Vec::new();
+
+    let mut stage = Stage::first(&players.position_config);
+    while stage != Stage::Done {
+        let mut distinct = HashSet::new();
+        let mut answers: HashMap<&str, usize> = HashMap::new();
+        for answered in &questions.questions {
+            let question = &answered.question;
+            if Stage::of_question(question).as_ref() != Some(&stage) {
+                continue;
+            }
+            // Put both sides of the question in a consistent order so reversed questions match
+            let mut sides = [
+                (question.player1.as_str(), question.pos1.to_str()),
+                (question.player2.as_str(), question.pos2.to_str()),
+            ];
+            sides.sort();
+            distinct.insert(sides);
+
+            *answers.entry(question.player1.as_str()).or_insert(0) += 1;
+            if question.player2 != question.player1 {
+                *answers.entry(question.player2.as_str()).or_insert(0) += 1;
+            }
+        }
+
+        // Only players who can be asked about in this stage count towards the least compared
+        let eligible: Vec<&Player> = players
+            .players
+            .iter()
+            .filter(|player| match &stage {
+                Stage::Position(pos) => player.can_play(pos),
+                _ => true,
+            })
+            .collect();
+        let count = |player: &Player| answers.get(player.id.as_str()).cloned().unwrap_or(0);
+        let least_answers = eligible.iter().map(|p| count(p)).min().unwrap_or(0);
+        let least_compared = eligible
+            .iter()
+            .filter(|p| count(p) == least_answers)
+            .map(|p| p.name.clone())
+            .collect();
+
+        let next = stage.next(&players.position_config);
+        coverage.push(StageCoverage {
+            possible: possible_questions(players, &stage),
+            stage,
+            answered: distinct.len(),
+            least_compared,
+            least_answers,
+        });
+        stage = next;
+    }
+    coverage
+}
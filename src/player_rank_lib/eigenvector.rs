@@ -0,0 +1,100 @@
+use crate::player_rank_lib::solver::{self, Comparison, PositionSkills};
+use crate::player_rank_lib::*;
+use std::collections::HashMap;
+
+const MAX_ITERATIONS: usize = 1000;
+const TOLERANCE: f64 = 1e-12;
+
+/// Rank players with the principal eigenvector of the pairwise comparison matrix, as in the
+/// analytic hierarchy process. Pairs nobody has compared are filled in with Harker's method, and
+/// pairs compared more than once use the weighted geometric mean of their answers
+pub struct Eigenvector;
+
+// Find the principal eigenvector of the comparison matrix for a single connected component
+fn component_eigenvector(members: &[usize], ratios: &HashMap<(usize, usize), f64>) -> Vec<f64> {
+    let n = members.len();
+    let mut matrix = vec![vec![0.0; n]; n];
+    for (a, &i) in members.iter().enumerate() {
+        let mut missing = 0;
+        for (b, &j) in members.iter().enumerate() {
+            if a == b {
+                continue;
+            }
+            match ratios.get(&(i, j)) {
+                Some(ratio) => matrix[a][b] = *ratio,
+                None => missing += 1,
+            }
+        }
+        // Harker's method: every missing comparison counts towards the diagonal instead
+        matrix[a][a] = 1.0 + missing as f64;
+    }
+
+    // Power iteration, the matrix is positive so this converges on the principal eigenvector
+    let mut vector = vec![1.0 / n as f64; n];
+    for _ in 0..MAX_ITERATIONS {
+        let mut next: Vec<f64> = matrix
+            .iter()
+            .map(|row| row.iter().zip(&vector).map(|(a, v)| a * v).sum())
+            .collect();
+        let total: f64 = next.iter().sum();
+        next.iter_mut().for_each(|v| *v /= total);
+
+        let change = next
+            .iter()
+            .zip(&vector)
+            .map(|(a, b)| (a - b).abs())
+            .fold(0.0, f64::max);
+        vector = next;
+        if change < TOLERANCE {
+            break;
+        }
+    }
+    vector
+}
+
+fn solve_position(num_players: usize, comparisons: &[Comparison]) -> Result<PositionSkills> {
+    // Combine repeated comparisons of the same pair with a weighted geometric mean
+    let mut log_ratio_totals: HashMap<(usize, usize), (f64, f64)> = HashMap::new();
+    for comparison in comparisons {
+        for (key, log_ratio) in [
+            ((comparison.lhs, comparison.rhs), comparison.log_ratio),
+            ((comparison.rhs, comparison.lhs), -comparison.log_ratio),
+        ] {
+            let total = log_ratio_totals.entry(key).or_insert((0.0, 0.0));
+            total.0 += comparison.weight * log_ratio;
+            total.1 += comparison.weight;
+        }
+    }
+    let ratios: HashMap<(usize, usize), f64> = log_ratio_totals
+        .into_iter()
+        .map(|(key, (sum, weight))| (key, (sum / weight).exp()))
+        .collect();
+
+    // Components that aren't linked can't be compared, so solve each one on its own
+    let component = solver::connected_components(num_players, comparisons);
+    let mut members: HashMap<usize, Vec<usize>> = HashMap::new();
+    for (i, c) in component.iter().enumerate() {
+        members.entry(*c).or_default().push(i);
+    }
+
+    let mut log_skills = vec![0.0; num_players];
+    for members in members.values() {
+        let vector = component_eigenvector(members, &ratios);
+        for (i, v) in members.iter().zip(vector) {
+            log_skills[*i] = v.ln();
+        }
+    }
+    solver::center_components(&mut log_skills, &component);
+
+    Ok(PositionSkills {
+        log_skills,
+        std_errs: vec![None; num_players],
+        degree: solver::comparison_degrees(num_players, comparisons),
+    })
+}
+
+impl RankingSolver for Eigenvector {
+    fn rank(&self, players: &Players, questions: &Questions) -> Result<Ranks> {
+        solver::rank_by_position(players, questions, solve_position)
+    }
+}
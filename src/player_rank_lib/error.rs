@@ -0,0 +1,85 @@
+use std::fmt;
+
+/// Everything that can go wrong in player_rank_lib, so callers can tell failures apart
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// There aren't enough players to do what was asked
+    TooFewPlayers { needed: usize, found: usize },
+    /// Nobody in the player list goes by this id, name or alias
+    UnknownPlayer(String),
+    /// A saved session refers to a player by their place in a player list that's too short
+    InvalidPlayerIndex { index: usize, players: usize },
+    /// More than one player has this id
+    DuplicateId(String),
+    /// A name or alias that could mean either of two players
+    AmbiguousReference {
+        reference: String,
+        players: (String, String),
+    },
+    /// Responses are ratios of skills, so they have to be positive and finite
+    InvalidResponse(f64),
+    /// There's no answered question at this place in the list of answers
+    UnknownQuestion(usize),
+    /// A response was given while no question was waiting for one
+    NoActiveQuestion,
+    /// Sections can't be skipped until the minimum question set has been asked
+    MinSetNotReached,
+    /// Every stage is done, there's no section to skip to
+    AllQuestionsAsked,
+    /// There are no ranks for this player
+    NoRank(String),
+    /// The answers don't pin the skills down to a single solution
+    Unsolvable,
+    /// Teams were asked for, but zero of them
+    NoTeams,
+    /// The player file doesn't have availability for this week, counting from 1
+    UnknownWeek(usize),
+}
+
+/// Result with player_rank_lib's Error
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::TooFewPlayers { needed, found } => {
+                write!(
+                    f,
+                    "Need at least {} players, but there are {}",
+                    needed, found
+                )
+            }
+            Error::UnknownPlayer(reference) => write!(f, "There's no player `{}`", reference),
+            Error::InvalidPlayerIndex { index, players } => write!(
+                f,
+                "The saved session refers to player {}, but only has {} players",
+                index, players
+            ),
+            Error::DuplicateId(id) => write!(f, "More than one player has the id `{}`", id),
+            Error::AmbiguousReference {
+                reference,
+                players: (first, second),
+            } => write!(f, "`{}` could mean `{}` or `{}`", reference, first, second),
+            Error::InvalidResponse(response) => write!(
+                f,
+                "Invalid response {}, it has to be a positive number",
+                response
+            ),
+            Error::UnknownQuestion(index) => {
+                write!(f, "There's no answered question number {}", index)
+            }
+            Error::NoActiveQuestion => write!(f, "There's no question waiting for a response"),
+            Error::MinSetNotReached => write!(
+                f,
+                "Can't skip sections until the minimum question set has been reached"
+            ),
+            Error::AllQuestionsAsked => write!(f, "All questions have been asked"),
+            Error::NoRank(name) => write!(f, "No rank for player `{}`", name),
+            Error::Unsolvable => write!(f, "Comparison system could not be solved"),
+            Error::NoTeams => write!(f, "Need at least one team"),
+            Error::UnknownWeek(week) => write!(f, "There's no availability for week {}", week),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
@@ -2,8 +2,30 @@ mod players;
 mod questions;
 mod ranks;
 mod player_rank;
+mod solver;
+mod consistency;
+mod eigenvector;
+mod bradley_terry;
+mod teams;
+mod coverage;
+mod agreement;
+mod strategy;
+mod connectivity;
+mod validation;
+mod error;
 
 pub use players::*;
 pub use ranks::*;
 pub use questions::*;
 pub use player_rank::*;
+pub use solver::*;
+pub use consistency::*;
+pub use eigenvector::*;
+pub use bradley_terry::*;
+pub use teams::*;
+pub use coverage::*;
+pub use agreement::*;
+pub use strategy::*;
+pub use connectivity::*;
+pub use validation::*;
+pub use error::*;
@@ -1,71 +1,93 @@
 use crate::player_rank_lib::*;
-use anyhow::Result;
 use rand::rngs::StdRng;
 use rand::{seq::SliceRandom, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
 use std::collections::hash_map::Entry;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
-pub struct PlayerRank<'a> {
-    players: &'a Players,
-    questions: &'a mut Questions,
+/// Works out which questions to ask, and ranks the players from the answers. Owns the player
+/// list and every answered question, including ones from previous sessions
+pub struct PlayerRank {
+    players: Players,
+    questions: Questions,
     // Curent stage of questioning
     stage: Stage,
     // If we've reached a minimum set of questions to compute a ranking
     minimum_set_reached: bool,
+    // If we've moved into the first stage yet
+    questioning_started: bool,
     // Queue of questions ready to be asked
-    min_set_question_queue: Vec<RefQuestion<'a>>,
-    current_question: Option<RefQuestion<'a>>,
-    skipped_questions: HashMap<Stage, Vec<RefQuestion<'a>>>,
-    answered_questions: HashMap<Stage, Vec<RefQuestion<'a>>>,
+    min_set_question_queue: Vec<IndexQuestion>,
+    current_question: Option<IndexQuestion>,
+    skipped_questions: HashMap<Stage, Vec<IndexQuestion>>,
+    answered_questions: HashMap<Stage, Vec<IndexQuestion>>,
     minimum_linkage: HashMap<Stage, usize>,
+    // Who's answering the questions
+    rater: Option<String>,
+    // Picks which question to ask next
+    strategy: Box<dyn QuestionStrategy>,
     // Used for randomizing question order
     rng: rand::rngs::StdRng,
 }
 
-#[derive(PartialEq, Copy, Clone)]
-struct RefQuestion<'a> {
-    pub player1: &'a Player,
+// A question with the players referred to by their index in the player list
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+struct IndexQuestion {
+    pub player1: usize,
     pub pos1: Position,
-    pub player2: &'a Player,
+    pub player2: usize,
     pub pos2: Position,
 }
 
-// Add method to create a Question from a RefQuestion
-impl Question {
-    fn from_opt_refq(q: &Option<RefQuestion>) -> Option<Self> {
-        if let Some(q) = q {
-            Some(Question {
-                player1: q.player1.name.clone(),
-                pos1: q.pos1,
-                player2: q.player2.name.clone(),
-                pos2: q.pos2,
-            })
-        } else {
-            None
-        }
+/// Everything needed to pick a questioning session back up exactly where it was left, including
+/// skipped questions and the questions still waiting to be asked
+#[derive(Serialize, Deserialize)]
+pub struct PlayerRankState {
+    players: Players,
+    questions: Questions,
+    stage: Stage,
+    minimum_set_reached: bool,
+    questioning_started: bool,
+    min_set_question_queue: Vec<IndexQuestion>,
+    current_question: Option<IndexQuestion>,
+    // Maps with stage keys are stored as lists of pairs, since JSON keys have to be strings
+    skipped_questions: Vec<(Stage, Vec<IndexQuestion>)>,
+    answered_questions: Vec<(Stage, Vec<IndexQuestion>)>,
+    minimum_linkage: Vec<(Stage, usize)>,
+    #[serde(default)]
+    rater: Option<String>,
+    // Missing for strategies that can't be saved. States from before there were strategies saved
+    // how questions were selected, with the same names
+    #[serde(default, alias = "selection")]
+    strategy: Option<SavedStrategy>,
+    // The random number generator carries on from this seed
+    seed: u64,
+}
+
+impl PlayerRankState {
+    // The players the session was saved with
+    pub fn players(&self) -> &Players {
+        &self.players
     }
 
-    fn from_refq(q: &RefQuestion) -> Self {
-        Question {
-            player1: q.player1.name.clone(),
-            pos1: q.pos1,
-            player2: q.player2.name.clone(),
-            pos2: q.pos2,
-        }
+    // Every question answered or skipped by the time the session was saved
+    pub fn questions(&self) -> &Questions {
+        &self.questions
     }
 }
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum QuestionStatus {
     StartingStage(Stage),
-    // Need to pass the stage back because this coincides with starting a new stage regretably
+    // Need to pass the stage back because this coincides with starting a new stage regretably.
+    // It's the stage the next question comes from, or Done if there's nothing left to ask
     AllMandatoryQuestionsAnswered(Stage), // TODO: This is just a connection level of 1. Are there other statuses we'd pass back?
     AllQuestionsSkipped,
     ConnectionLevelReached(usize),
 }
 
 // Question asking is broken into stages, these are them
-#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy)]
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Serialize, Deserialize)]
 pub enum Stage {
     Position(Position),
     SelfRating,
@@ -74,79 +96,256 @@ pub enum Stage {
 
 impl Stage {
     // The first stage in the ordering
-    pub fn first() -> Self {
-        Stage::Position(Position::Atk)
+    pub fn first(config: &PositionConfig) -> Self {
+        match config.positions.first() {
+            Some(info) => Stage::Position(info.position.clone()),
+            None => Stage::SelfRating,
+        }
     }
 
-    // The stages have an ordering. This method defines that ordering
-    pub fn next(&self) -> Self {
+    // The stages have an ordering: each position in the order of the config, then self ratings
+    pub fn next(&self, config: &PositionConfig) -> Self {
         match self {
-            Stage::Position(pos) => match pos {
-                Position::Atk => Stage::Position(Position::Def),
-                Position::Def => Stage::Position(Position::Goalie),
-                Position::Goalie => Stage::SelfRating,
+            Stage::Position(pos) => match config.after(pos) {
+                Some(next) => Stage::Position(next),
+                None => Stage::SelfRating,
             },
             Stage::SelfRating => Stage::Done,
             Stage::Done => Stage::Done, // Stay in Done forever
         }
     }
-}
 
-pub enum NextSectionError {
-    MinSetNotReached,
-    AllQuestionsAsked,
+    // The stage a question gets asked in, if it belongs to any
+    pub fn of_question(question: &Question) -> Option<Self> {
+        if question.player1 != question.player2 && question.pos1 == question.pos2 {
+            Some(Stage::Position(question.pos1.clone()))
+        } else if question.player1 == question.player2 && question.pos1 != question.pos2 {
+            Some(Stage::SelfRating)
+        } else {
+            None
+        }
+    }
 }
 
-pub enum ResponseError {
-    NoActiveQuestion,
-    InvalidResponse,
-}
+// Fewer players than this don't leave enough pairs to be worth ranking
+const MIN_PLAYERS: usize = 4;
 
-impl<'a> PlayerRank<'a> {
-    pub fn new(players: &'a Players, questions: &'a mut Questions, seed: Option<u64>) -> Self {
-        // TODO: Handle non-empty question files, or at least make this nicer
-        if !questions.questions.is_empty() {
-            panic!("I cannot handle non-empty question files");
+impl PlayerRank {
+    pub fn new(players: Players, questions: Questions, seed: Option<u64>) -> Result<Self> {
+        if players.players.len() < MIN_PLAYERS {
+            return Err(Error::TooFewPlayers {
+                needed: MIN_PLAYERS,
+                found: players.players.len(),
+            });
         }
-
         let rng = match seed {
             Some(s) => StdRng::seed_from_u64(s),
             None => StdRng::from_entropy(),
         };
 
-        PlayerRank {
+        let mut player_rank = PlayerRank {
+            stage: Stage::first(&players.position_config),
             players,
             questions,
-            stage: Stage::first(),
             minimum_set_reached: false,
+            questioning_started: false,
             min_set_question_queue: Vec::new(),
             current_question: None,
             skipped_questions: HashMap::new(),
             answered_questions: HashMap::new(),
             minimum_linkage: HashMap::new(),
+            rater: None,
+            strategy: Box::new(FewestConnections),
             rng,
+        };
+        player_rank.replay_answered_questions();
+        Ok(player_rank)
+    }
+
+    // Sort questions answered in previous sessions into their stages, so we pick up where we left off
+    fn replay_answered_questions(&mut self) {
+        let answered: Vec<Question> = self
+            .questions
+            .questions
+            .iter()
+            .map(|answered| answered.question.clone())
+            .collect();
+        for (stage, question) in self.index_questions(&answered) {
+            self.answered_questions
+                .entry(stage)
+                .or_default()
+                .push(question);
+        }
+
+        // Skipped questions don't get asked again
+        let skipped = self.questions.skipped.clone();
+        for (stage, question) in self.index_questions(&skipped) {
+            self.skipped_questions
+                .entry(stage)
+                .or_default()
+                .push(question);
         }
     }
 
-    fn get_shuffled_player_list(&mut self) -> Vec<&'a Player> {
-        // Create shuffled list of all players
-        let mut player_list: Vec<&Player> = Vec::new();
-        for player in &self.players.players {
-            player_list.push(&player);
+    // Convert questions to refer to players by index, along with the stage they belong to.
+    // Questions about players we don't know about anymore, or about positions they can't play
+    // anymore, can't be asked again anyway, so they're left out
+    fn index_questions(&self, questions: &[Question]) -> Vec<(Stage, IndexQuestion)> {
+        let mut indexed = Vec::new();
+        for question in questions {
+            let (player1, player2) = match (
+                self.players.index_of(&question.player1),
+                self.players.index_of(&question.player2),
+            ) {
+                (Ok(player1), Ok(player2)) => (player1, player2),
+                _ => continue,
+            };
+            let stage = match Stage::of_question(question) {
+                Some(stage) => stage,
+                None => continue,
+            };
+            let can_play = |player: usize, pos| self.players.players[player].can_play(pos);
+            if !can_play(player1, &question.pos1) || !can_play(player2, &question.pos2) {
+                continue;
+            }
+            indexed.push((
+                stage,
+                IndexQuestion {
+                    player1,
+                    pos1: question.pos1.clone(),
+                    player2,
+                    pos2: question.pos2.clone(),
+                },
+            ));
         }
+        indexed
+    }
+
+    // If a question, or the same question the other way around, has been skipped
+    fn was_skipped(&self, stage: Stage, question: &IndexQuestion) -> bool {
+        let reversed = IndexQuestion {
+            player1: question.player2,
+            pos1: question.pos2.clone(),
+            player2: question.player1,
+            pos2: question.pos1.clone(),
+        };
+        self.skipped_questions
+            .get(&stage)
+            .is_some_and(|skipped| skipped.contains(question) || skipped.contains(&reversed))
+    }
+
+    // Convert a question back to one that names its players
+    fn to_question(&self, question: &IndexQuestion) -> Question {
+        Question {
+            player1: self.players.players[question.player1].id.clone(),
+            pos1: question.pos1.clone(),
+            player2: self.players.players[question.player2].id.clone(),
+            pos2: question.pos2.clone(),
+        }
+    }
+
+    // Label each player with the group of players already linked to it by answered questions in a stage
+    fn answered_components(&self, stage: Stage) -> Vec<usize> {
+        let mut component: Vec<usize> = (0..self.players.players.len()).collect();
+        if let Some(answered) = self.answered_questions.get(&stage) {
+            for question in answered {
+                let from = component[question.player1];
+                let to = component[question.player2];
+                // Merge the two groups
+                for c in component.iter_mut() {
+                    if *c == from {
+                        *c = to;
+                    }
+                }
+            }
+        }
+        component
+    }
+
+    fn get_shuffled_player_list(&mut self) -> Vec<usize> {
+        // Create shuffled list of all players
+        let mut player_list: Vec<usize> = (0..self.players.players.len()).collect();
 
         player_list.shuffle(&mut self.rng);
         player_list
     }
 
-    // Generate a randomized, minimum set of questions to fully define the vector space(idk if that means anything but it sounds sick lmao)
-    fn min_set_populate_position(&mut self, pos: Position) {
-        let player_list = self.get_shuffled_player_list();
+    // Shuffled list of only the players who can play a position
+    fn get_shuffled_eligible_players(&mut self, pos: &Position) -> Vec<usize> {
+        let mut player_list = self.get_shuffled_player_list();
+        player_list.retain(|&player| self.players.players[player].can_play(pos));
+        player_list
+    }
+
+    // Generate a randomized, minimum set of questions to fully define the vector space(idk if that means anything but it sounds sick lmao).
+    // Returns false if some players can't be linked to the rest, because every question that
+    // would link them has been skipped
+    fn min_set_populate_position(&mut self, pos: &Position) -> bool {
+        let eligible = self.get_shuffled_eligible_players(pos);
+
+        // Players already linked by previously answered questions only need one link to everyone
+        // else, so only keep the first player we see from each linked group
+        let component = self.answered_components(Stage::Position(pos.clone()));
+        let mut seen_components = HashSet::new();
+        let player_list: Vec<usize> = eligible
+            .iter()
+            .copied()
+            .filter(|&player| seen_components.insert(component[player]))
+            .collect();
+        let members = |player: usize| -> Vec<usize> {
+            eligible
+                .iter()
+                .copied()
+                .filter(|&other| component[other] == component[player])
+                .collect()
+        };
+        let skipped = |player1, player2| {
+            let question = IndexQuestion {
+                player1,
+                pos1: pos.clone(),
+                player2,
+                pos2: pos.clone(),
+            };
+            self.was_skipped(Stage::Position(pos.clone()), &question)
+        };
 
-        // Create pairs from this shuffled list
-        let mut pairs: Vec<(&Player, &Player)> = Vec::new();
-        for i in 0..(player_list.len() - 1) {
-            pairs.push((player_list[i], player_list[i + 1]));
+        // Create pairs from this shuffled list. Link each player to the one before it, or to an
+        // earlier player if that question was skipped in the past, or failing that to anyone
+        // already linked to either of them. This builds a spanning forest, so a player whose
+        // questions were all skipped only leaves themselves out, not everyone after them
+        fn root(group: &[usize], mut i: usize) -> usize {
+            while group[i] != i {
+                i = group[i];
+            }
+            i
+        }
+        let mut group: Vec<usize> = (0..player_list.len()).collect();
+        let mut groups = player_list.len();
+        let mut pairs: Vec<(usize, usize)> = Vec::new();
+        for through_members in [false, true] {
+            for i in 1..player_list.len() {
+                for j in (0..i).rev() {
+                    let (earlier_root, player_root) = (root(&group, j), root(&group, i));
+                    if earlier_root == player_root {
+                        continue;
+                    }
+                    let (earlier, player) = (player_list[j], player_list[i]);
+                    let pair = if through_members {
+                        let rhs_members = members(player);
+                        members(earlier)
+                            .into_iter()
+                            .flat_map(|lhs| rhs_members.iter().map(move |&rhs| (lhs, rhs)))
+                            .find(|&(lhs, rhs)| !skipped(lhs, rhs))
+                    } else {
+                        Some((earlier, player)).filter(|&(lhs, rhs)| !skipped(lhs, rhs))
+                    };
+                    if let Some(pair) = pair {
+                        pairs.push(pair);
+                        group[earlier_root] = player_root;
+                        groups -= 1;
+                    }
+                }
+            }
         }
 
         // Shuffle those pairs
@@ -156,68 +355,99 @@ impl<'a> PlayerRank<'a> {
 
         // Convert the player pairs into references
         for pair in pairs {
-            let question = RefQuestion {
+            let question = IndexQuestion {
                 player1: pair.0,
-                pos1: pos,
+                pos1: pos.clone(),
                 player2: pair.1,
-                pos2: pos,
+                pos2: pos.clone(),
             };
             temp_questions.push(question);
         }
 
         self.min_set_question_queue.extend(temp_questions);
+        groups <= 1
     }
 
     fn min_set_populate_self(&mut self) {
         let player_list = self.get_shuffled_player_list();
 
-        assert!(player_list.len() > 3);
+        // Every position gets linked to the first one. Positions already linked to it by
+        // previously answered self ratings don't need asking
+        let positions = self.players.position_config.list();
+        let first = match positions.first() {
+            Some(first) => first.clone(),
+            None => return,
+        };
+        let mut linked = vec![first.clone()];
+        if let Some(answered) = self.answered_questions.get(&Stage::SelfRating) {
+            // Keep going until no more positions get linked
+            let mut found = true;
+            while found {
+                found = false;
+                for question in answered {
+                    let (has1, has2) = (
+                        linked.contains(&question.pos1),
+                        linked.contains(&question.pos2),
+                    );
+                    if has1 != has2 {
+                        let pos = if has1 { &question.pos2 } else { &question.pos1 };
+                        linked.push(pos.clone());
+                        found = true;
+                    }
+                }
+            }
+        }
 
         let mut temp_questions = Vec::new(); // Temporary vector to collect questions
 
-        // Attack-Defense
-        temp_questions.push(RefQuestion {
-            player1: player_list[0],
-            pos1: Position::Atk,
-            player2: player_list[0],
-            pos2: Position::Def,
-        });
-
-        // Attack-Goalie
-        temp_questions.push(RefQuestion {
-            player1: player_list[0],
-            pos1: Position::Atk,
-            player2: player_list[0],
-            pos2: Position::Goalie,
-        });
+        // Only players who can play a position get asked about playing it
+        for pos in &positions[1..] {
+            if linked.contains(pos) {
+                continue;
+            }
+            let question = |player| IndexQuestion {
+                player1: player,
+                pos1: first.clone(),
+                player2: player,
+                pos2: pos.clone(),
+            };
+            if let Some(&player) = player_list.iter().find(|&&player| {
+                let can_play = |pos| self.players.players[player].can_play(pos);
+                can_play(&first)
+                    && can_play(pos)
+                    && !self.was_skipped(Stage::SelfRating, &question(player))
+            }) {
+                temp_questions.push(question(player));
+            }
+        }
 
         self.min_set_question_queue.extend(temp_questions);
     }
 
-    fn populate_min_set_queue(&mut self) {
+    // Returns false if the minimum set can't link everyone, since too much has been skipped
+    fn populate_min_set_queue(&mut self) -> bool {
         // Populate with minimum set for the stage
-        match self.stage {
-            Stage::Position(pos) => self.min_set_populate_position(pos),
-            Stage::SelfRating => self.min_set_populate_self(),
-            Stage::Done => { /* Nothing to populate */ }
+        match self.stage.clone() {
+            Stage::Position(pos) => self.min_set_populate_position(&pos),
+            Stage::SelfRating => {
+                self.min_set_populate_self();
+                true
+            }
+            Stage::Done => true, // Nothing to populate
         }
     }
 
-    fn get_skip_replacement_position(&self) -> Option<RefQuestion<'a>> {
-        let pos = match self.stage {
-            Stage::Position(pos) => pos,
+    fn get_skip_replacement_position(&mut self) -> Option<IndexQuestion> {
+        let pos = match &self.stage {
+            Stage::Position(pos) => pos.clone(),
             _ => return None,
         };
 
-        let curr_q = if let Some(curr_q) = self.current_question {
-            curr_q
-        } else {
-            return None;
-        };
+        let curr_q = self.current_question.clone()?;
 
         // Find all numbers connected to each number in the skipped question
-        let mut lhs: Vec<&Player> = vec![curr_q.player1];
-        let mut rhs: Vec<&Player> = vec![curr_q.player2];
+        let mut lhs: Vec<usize> = vec![curr_q.player1];
+        let mut rhs: Vec<usize> = vec![curr_q.player2];
 
         // Create a list of all the asked and about-to-be-asked questions, not including the skipped question
         let mut all_questions = self.min_set_question_queue.clone(); // Only ever contain questions for a single stage
@@ -226,10 +456,13 @@ impl<'a> PlayerRank<'a> {
         }
 
         // Iterate through all the questions
-        // If one of them connects to a known number, add it to the list and start looking for another
-        while !all_questions.is_empty() {
+        // If one of them connects to a known number, add it to the list and start looking for another.
+        // Stop once a full pass links nothing new, the rest don't touch either side
+        let mut linked = true;
+        while linked && !all_questions.is_empty() {
+            linked = false;
             for i in (0..all_questions.len()).rev() {
-                let pair = all_questions[i];
+                let pair = all_questions[i].clone();
                 let mut found = false;
                 if lhs.contains(&pair.player1) {
                     lhs.push(pair.player2);
@@ -246,26 +479,27 @@ impl<'a> PlayerRank<'a> {
                 }
                 if found {
                     all_questions.remove(i);
+                    linked = true;
                     break;
                 }
             }
         }
 
         // Generate list of all potential replacement questions, not including the skipped ones
-        let mut potential_replacements: Vec<RefQuestion> = Vec::new();
-        for left in &lhs {
-            for right in &rhs {
-                let potential_question = RefQuestion {
-                    player1: *left,
-                    pos1: pos,
-                    player2: *right,
-                    pos2: pos,
+        let mut potential_replacements: Vec<IndexQuestion> = Vec::new();
+        for &left in &lhs {
+            for &right in &rhs {
+                let potential_question = IndexQuestion {
+                    player1: left,
+                    pos1: pos.clone(),
+                    player2: right,
+                    pos2: pos.clone(),
                 };
-                let potential_question_rev = RefQuestion {
-                    player1: *right,
-                    pos1: pos,
-                    player2: *left,
-                    pos2: pos,
+                let potential_question_rev = IndexQuestion {
+                    player1: right,
+                    pos1: pos.clone(),
+                    player2: left,
+                    pos2: pos.clone(),
                 };
                 if self.skipped_questions.contains_key(&self.stage)
                     && !self.skipped_questions[&self.stage].contains(&potential_question)
@@ -282,35 +516,34 @@ impl<'a> PlayerRank<'a> {
         }
 
         // Choose a random one and add it to the upcoming list
-        let rand = rand::random::<usize>() % potential_replacements.len();
-        Some(potential_replacements[rand])
+        potential_replacements.choose(&mut self.rng).cloned()
     }
 
-    fn get_skip_replacement_self_rating(&mut self) -> Option<RefQuestion<'a>> {
+    fn get_skip_replacement_self_rating(&mut self) -> Option<IndexQuestion> {
         // Make sure we're in the right stage
         if self.stage != Stage::SelfRating {
             return None;
         }
 
         // Make sure there's a question being skipped
-        let curr_q = if let Some(curr_q) = self.current_question {
-            curr_q
-        } else {
-            return None;
-        };
+        let curr_q = self.current_question.clone()?;
 
         // Shuffle up the players
         let player_list = self.get_shuffled_player_list();
 
-        // There's one possible replacement question for each player.
+        // There's one possible replacement question for each player who can play both positions.
         // Try each player, make sure it hasn't been skipped already
         for player in player_list {
+            let can_play = |pos| self.players.players[player].can_play(pos);
+            if !can_play(&curr_q.pos1) || !can_play(&curr_q.pos2) {
+                continue;
+            }
             // The potential replacement question for the skipped question
-            let potential_question = RefQuestion {
+            let potential_question = IndexQuestion {
                 player1: player,
-                pos1: curr_q.pos1,
+                pos1: curr_q.pos1.clone(),
                 player2: player,
-                pos2: curr_q.pos2,
+                pos2: curr_q.pos2.clone(),
             };
 
             // If we've already answered this question
@@ -333,7 +566,7 @@ impl<'a> PlayerRank<'a> {
     }
 
     // When a question gets skipped, find a question to replace it while maintaining the requirements of a fully connected graph
-    fn get_skip_replacement(&mut self) -> Option<RefQuestion<'a>> {
+    fn get_skip_replacement(&mut self) -> Option<IndexQuestion> {
         match self.stage {
             Stage::Position(_) => self.get_skip_replacement_position(),
             Stage::SelfRating => self.get_skip_replacement_self_rating(),
@@ -342,7 +575,7 @@ impl<'a> PlayerRank<'a> {
     }
 
     // For a given question, determine how many other answered questions reference each player in the question
-    fn count_connections(&self, question: &RefQuestion) -> usize {
+    fn count_connections(&self, question: &IndexQuestion) -> usize {
         let mut left_cnt = 0;
         let mut right_cnt = 0;
 
@@ -361,26 +594,31 @@ impl<'a> PlayerRank<'a> {
         left_cnt + right_cnt
     }
 
-    fn list_remaining_questions_position(&mut self) -> Vec<RefQuestion<'a>> {
-        let pos = match self.stage {
-            Stage::Position(pos) => pos,
+    fn list_remaining_questions_position(&mut self) -> Vec<IndexQuestion> {
+        let pos = match &self.stage {
+            Stage::Position(pos) => pos.clone(),
             _ => return Vec::new(),
         };
 
-        let mut remaining_questions: Vec<RefQuestion> = Vec::new();
+        let mut remaining_questions: Vec<IndexQuestion> = Vec::new();
         for p1 in 0..self.players.players.len() {
             for p2 in (p1 + 1)..self.players.players.len() {
-                let question = RefQuestion {
-                    player1: &self.players.players[p1],
-                    pos1: pos,
-                    player2: &self.players.players[p2],
-                    pos2: pos,
+                if !self.players.players[p1].can_play(&pos)
+                    || !self.players.players[p2].can_play(&pos)
+                {
+                    continue;
+                }
+                let question = IndexQuestion {
+                    player1: p1,
+                    pos1: pos.clone(),
+                    player2: p2,
+                    pos2: pos.clone(),
                 };
-                let question_rev = RefQuestion {
-                    player1: &self.players.players[p2],
-                    pos1: pos,
-                    player2: &self.players.players[p1],
-                    pos2: pos,
+                let question_rev = IndexQuestion {
+                    player1: p2,
+                    pos1: pos.clone(),
+                    player2: p1,
+                    pos2: pos.clone(),
                 };
 
                 // If we've already answered this question, ignore it
@@ -393,7 +631,7 @@ impl<'a> PlayerRank<'a> {
 
                 // If we've already skipped this question, ignore it
                 if self.skipped_questions.contains_key(&self.stage)
-                    && (!self.skipped_questions[&self.stage].contains(&question)
+                    && (self.skipped_questions[&self.stage].contains(&question)
                         || self.skipped_questions[&self.stage].contains(&question_rev))
                 {
                     continue;
@@ -408,39 +646,36 @@ impl<'a> PlayerRank<'a> {
         remaining_questions
     }
 
-    fn list_remaining_questions_self_rating(&mut self) -> Vec<RefQuestion<'a>> {
+    fn list_remaining_questions_self_rating(&mut self) -> Vec<IndexQuestion> {
         let player_list = self.get_shuffled_player_list();
+        let positions = self.players.position_config.list();
 
         // Go through all the possible questions, and filter out the ones we've answered or skipped already
         let mut remaining_questions = Vec::new();
         for player in player_list {
-            let potential_questions: Vec<RefQuestion> = vec![
-                RefQuestion {
-                    player1: player,
-                    pos1: Position::Atk,
-                    player2: player,
-                    pos2: Position::Def,
-                },
-                RefQuestion {
-                    player1: player,
-                    pos1: Position::Atk,
-                    player2: player,
-                    pos2: Position::Goalie,
-                },
-                RefQuestion {
-                    player1: player,
-                    pos1: Position::Def,
-                    player2: player,
-                    pos2: Position::Goalie,
-                },
-            ];
+            // Every pair of positions, in config order
+            let mut potential_questions = Vec::new();
+            for (i, pos1) in positions.iter().enumerate() {
+                for pos2 in &positions[i + 1..] {
+                    potential_questions.push(IndexQuestion {
+                        player1: player,
+                        pos1: pos1.clone(),
+                        player2: player,
+                        pos2: pos2.clone(),
+                    });
+                }
+            }
 
             for pot_q in potential_questions {
-                let pot_q_rev = RefQuestion {
+                let can_play = |pos| self.players.players[player].can_play(pos);
+                if !can_play(&pot_q.pos1) || !can_play(&pot_q.pos2) {
+                    continue;
+                }
+                let pot_q_rev = IndexQuestion {
                     player1: pot_q.player1,
-                    pos1: pot_q.pos2,
+                    pos1: pot_q.pos2.clone(),
                     player2: pot_q.player2,
-                    pos2: pot_q.pos1,
+                    pos2: pot_q.pos1.clone(),
                 };
                 // If we've already answered this question, ignore it
                 if self.answered_questions.contains_key(&self.stage)
@@ -452,7 +687,7 @@ impl<'a> PlayerRank<'a> {
 
                 // If we've already skipped this question, ignore it
                 if self.skipped_questions.contains_key(&self.stage)
-                    && (!self.skipped_questions[&self.stage].contains(&pot_q)
+                    && (self.skipped_questions[&self.stage].contains(&pot_q)
                         || self.skipped_questions[&self.stage].contains(&pot_q_rev))
                 {
                     continue;
@@ -467,7 +702,24 @@ impl<'a> PlayerRank<'a> {
         remaining_questions
     }
 
-    fn get_regular_question(&mut self) -> (Option<RefQuestion<'a>>, Option<QuestionStatus>) {
+    // Let the strategy pick which of the candidates to ask next
+    fn choose_question(&mut self, candidates: &[IndexQuestion]) -> Option<usize> {
+        if candidates.is_empty() {
+            return None;
+        }
+        let named: Vec<Question> = candidates.iter().map(|q| self.to_question(q)).collect();
+        let context = QuestionContext {
+            players: &self.players,
+            questions: &self.questions,
+            stage: self.stage.clone(),
+            minimum_set_reached: self.minimum_set_reached,
+            candidates: &named,
+        };
+        let choice = self.strategy.choose(&context, &mut self.rng);
+        Some(choice.min(candidates.len() - 1))
+    }
+
+    fn get_regular_question(&mut self) -> (Option<IndexQuestion>, Option<QuestionStatus>) {
         let mut status = None;
 
         let remaining_questions = loop {
@@ -480,42 +732,40 @@ impl<'a> PlayerRank<'a> {
 
             if remaining_questions.is_empty() {
                 // Move to the next stage!
-                self.stage = self.stage.next();
+                self.stage = self.stage.next(&self.players.position_config);
 
                 // If we're done, we're not starting a new stage, otherwise update
                 // user on the new stage
                 match self.stage {
                     Stage::Done => return (None, None),
-                    _ => status = Some(QuestionStatus::StartingStage(self.stage)),
+                    _ => status = Some(QuestionStatus::StartingStage(self.stage.clone())),
                 }
                 continue;
             }
             break remaining_questions;
         };
 
-        // Find minimum linked question in the list
-        let mut min_links = self.players.players.len();
-        let mut min_question: Option<RefQuestion> = None;
-        for question in remaining_questions {
-            let pair_links = self.count_connections(&question) / 2;
-            if pair_links < min_links {
-                min_links = pair_links;
-                min_question = Some(question);
-            }
-        }
+        // Find how linked the least linked question in the list is
+        let min_links = remaining_questions
+            .iter()
+            .map(|question| self.count_connections(question) / 2)
+            .fold(self.players.players.len(), usize::min);
+        let min_question = self
+            .choose_question(&remaining_questions)
+            .map(|i| remaining_questions[i].clone());
 
         // Update user on how connected the graph is
         // This is sorta like a confidence measure
         let connection_level_status = {
             if !self.minimum_linkage.contains_key(&self.stage) {
-                self.minimum_linkage.insert(self.stage, min_links);
+                self.minimum_linkage.insert(self.stage.clone(), min_links);
             }
 
             let curr_min_links = self.minimum_linkage[&self.stage];
 
             if curr_min_links != min_links && min_question.is_some() {
                 // Update the minimum linkage
-                self.minimum_linkage.insert(self.stage, min_links);
+                self.minimum_linkage.insert(self.stage.clone(), min_links);
                 // Notify the user we've reached a new connection level
                 Some(QuestionStatus::ConnectionLevelReached(curr_min_links))
             } else {
@@ -531,55 +781,65 @@ impl<'a> PlayerRank<'a> {
         (min_question, status)
     }
 
-    fn get_min_set_question(&mut self) -> (Option<RefQuestion<'a>>, Option<QuestionStatus>) {
+    fn get_min_set_question(&mut self) -> (Option<IndexQuestion>, Option<QuestionStatus>) {
         let mut status = None;
-
-        if self.min_set_question_queue.is_empty() {
-
-            // If no questions have been asked, this is our first time through. Go to the first stage
-            self.stage = if self.skipped_questions.is_empty() && self.answered_questions.is_empty()
-            {
-                Stage::first()
+        let mut everyone_linked = true;
+
+        // Stages already covered by questions answered in a previous session have nothing to queue,
+        // so keep moving through stages until there's something to ask
+        while self.min_set_question_queue.is_empty() {
+            // If this is our first time through, go to the first stage
+            self.stage = if !self.questioning_started {
+                self.questioning_started = true;
+                Stage::first(&self.players.position_config)
             } else {
                 // Move to the next stage
-                self.stage.next()
+                self.stage.next(&self.players.position_config)
             };
 
             // If we're done, we're not starting a new stage, otherwise update
             // user on the new stage
             status = match self.stage {
                 Stage::Done => None,
-                _ => Some(QuestionStatus::StartingStage(self.stage)),
+                _ => Some(QuestionStatus::StartingStage(self.stage.clone())),
             };
 
             // Move from minimum questions to extra questions
             if self.stage == Stage::Done && !self.minimum_set_reached {
                 self.minimum_set_reached = true;
                 // Reset the stage for asking extra questions
-                self.stage = Stage::first();
-                // Inform user we're done min question set
-                status = Some(QuestionStatus::AllMandatoryQuestionsAnswered(self.stage));
-
-                // Now give the first regular question
-                let regular_question = self.get_regular_question();
-                if let Some(ignored_status) = regular_question.1 {
-                    println!("Warning, ignored status message {:?}", ignored_status);
-                }
-                return (regular_question.0, status);
+                self.stage = Stage::first(&self.players.position_config);
+
+                // Now give the first regular question. Stages with nothing left to ask get
+                // passed over, so tell the user which stage it comes from, rather than the first
+                let (question, _) = self.get_regular_question();
+                let status = QuestionStatus::AllMandatoryQuestionsAnswered(self.stage.clone());
+                return (question, Some(status));
             }
 
             // Populate queue based on the stage and min questions asked
-            self.populate_min_set_queue();
+            everyone_linked &= self.populate_min_set_queue();
         }
 
-        (self.min_set_question_queue.pop(), status)
+        // Let the user know that skipped questions left some players out, that's more important
+        // than the stage starting
+        if !everyone_linked {
+            status = Some(QuestionStatus::AllQuestionsSkipped);
+        }
+
+        let question = self
+            .choose_question(&self.min_set_question_queue.clone())
+            .map(|i| self.min_set_question_queue.remove(i));
+        (question, status)
     }
 
     pub fn get_next_question(&mut self) -> (Option<Question>, Option<QuestionStatus>) {
         // If there's still a current question, then it's being skipped, perform skipping logic
-        if let Some(current_question) = self.current_question {
-            // Add the current question to the skipped questions list
-            match self.skipped_questions.entry(self.stage) {
+        if let Some(current_question) = self.current_question.clone() {
+            // Add the current question to the skipped questions lists
+            let skipped = self.to_question(&current_question);
+            self.questions.skipped.push(skipped);
+            match self.skipped_questions.entry(self.stage.clone()) {
                 Entry::Occupied(mut entry) => {
                     entry.get_mut().push(current_question);
                 }
@@ -614,13 +874,14 @@ impl<'a> PlayerRank<'a> {
         };
 
         // Return the current question to the user
-        (Question::from_opt_refq(&self.current_question), status)
+        let question = self.current_question.as_ref().map(|q| self.to_question(q));
+        (question, status)
     }
 
-    pub fn next_section(&mut self) -> Result<(), NextSectionError> {
+    pub fn next_section(&mut self) -> Result<()> {
         if self.minimum_set_reached {
             if self.stage == Stage::Done {
-                Err(NextSectionError::AllQuestionsAsked)
+                Err(Error::AllQuestionsAsked)
             } else {
                 // Empty the question queue. The next time a question is requested, it'll move to
                 // the next section to refill the queue
@@ -628,19 +889,19 @@ impl<'a> PlayerRank<'a> {
                 Ok(())
             }
         } else {
-            Err(NextSectionError::MinSetNotReached)
+            Err(Error::MinSetNotReached)
         }
     }
 
-    pub fn give_response(&mut self, response: f64) -> Result<(), ResponseError> {
+    pub fn give_response(&mut self, response: f64) -> Result<()> {
         if let Some(question) = &self.current_question {
-            // Check that the response is allowed
-            if !response.is_finite() || response.is_sign_negative() {
-                Err(ResponseError::InvalidResponse)
+            // Check that the response is allowed. It's a ratio of skills, so it must be positive
+            if !response.is_finite() || response <= 0.0 {
+                Err(Error::InvalidResponse(response))
             } else {
                 // Add to our list of answered questions
                 // Add the current question to the skipped questions list
-                match self.answered_questions.entry(self.stage) {
+                match self.answered_questions.entry(self.stage.clone()) {
                     Entry::Occupied(mut entry) => {
                         entry.get_mut().push(question.clone());
                     }
@@ -650,9 +911,10 @@ impl<'a> PlayerRank<'a> {
                 }
 
                 // Also add to the user-facing list of answered questions
+                let question = self.to_question(question);
                 self.questions.questions.push(AnsweredQuestion {
-                    question: Question::from_refq(question),
-                    response,
+                    rater: self.rater.clone(),
+                    ..AnsweredQuestion::new(question, response)
                 });
 
                 // Clear the current question
@@ -661,25 +923,141 @@ impl<'a> PlayerRank<'a> {
                 Ok(())
             }
         } else {
-            Err(ResponseError::NoActiveQuestion)
+            Err(Error::NoActiveQuestion)
+        }
+    }
+
+    // Change the response to a question answered earlier, like when it gets asked again because
+    // it contradicts the other answers. index is its place in questions()
+    pub fn answer_again(&mut self, index: usize, response: f64) -> Result<()> {
+        if !response.is_finite() || response <= 0.0 {
+            return Err(Error::InvalidResponse(response));
         }
+        let answered = self
+            .questions
+            .questions
+            .get_mut(index)
+            .ok_or(Error::UnknownQuestion(index))?;
+        answered.response = response;
+        Ok(())
     }
 
     pub fn get_ranking(&self) -> Result<Ranks> {
-        let mut ranks = Ranks::new();
-        // Add some test ranks for now
-        ranks.ranks.push(Rank {
-            name: String::from("player1 Name"),
-            atk: 1.2,
-            def: 1.5,
-            goalie: Some(2.2),
-        });
-        ranks.ranks.push(Rank {
-            name: String::from("player2 Name"),
-            atk: 0.8,
-            def: 1.24,
-            goalie: None,
-        });
-        Ok(ranks)
+        self.get_ranking_with(&LogLeastSquares)
+    }
+
+    pub fn get_ranking_with(&self, solver: &dyn RankingSolver) -> Result<Ranks> {
+        solver.rank(&self.players, &self.questions)
+    }
+
+    // Save the complete state of questioning. The random number generator is reseeded so this
+    // session carries on exactly the same way a session restored from the state would
+    pub fn save_state(&mut self) -> PlayerRankState {
+        let seed = self.rng.gen::<u64>();
+        self.rng = StdRng::seed_from_u64(seed);
+
+        PlayerRankState {
+            players: self.players.clone(),
+            questions: self.questions.clone(),
+            stage: self.stage.clone(),
+            minimum_set_reached: self.minimum_set_reached,
+            questioning_started: self.questioning_started,
+            min_set_question_queue: self.min_set_question_queue.clone(),
+            current_question: self.current_question.clone(),
+            skipped_questions: self.skipped_questions.clone().into_iter().collect(),
+            answered_questions: self.answered_questions.clone().into_iter().collect(),
+            minimum_linkage: self.minimum_linkage.clone().into_iter().collect(),
+            rater: self.rater.clone(),
+            strategy: self.strategy.save(),
+            seed,
+        }
+    }
+
+    // Pick up a session saved with save_state. A question that was waiting for a response is
+    // still waiting for one
+    pub fn from_state(state: PlayerRankState) -> Result<Self> {
+        // Every question has to be about someone in the player list. The ones waiting to be asked
+        // refer to players by their place in it, so a state that's been edited by hand could point
+        // past the end of it
+        let answered = state
+            .questions
+            .questions
+            .iter()
+            .map(|answered| &answered.question);
+        for question in answered.chain(&state.questions.skipped) {
+            state.players.get(&question.player1)?;
+            state.players.get(&question.player2)?;
+        }
+        let num_players = state.players.players.len();
+        let index_questions = state
+            .min_set_question_queue
+            .iter()
+            .chain(&state.current_question)
+            .chain(
+                state
+                    .skipped_questions
+                    .iter()
+                    .chain(&state.answered_questions)
+                    .flat_map(|(_, questions)| questions),
+            );
+        if let Some(index) = index_questions
+            .flat_map(|question| [question.player1, question.player2])
+            .find(|&index| index >= num_players)
+        {
+            return Err(Error::InvalidPlayerIndex {
+                index,
+                players: num_players,
+            });
+        }
+
+        Ok(PlayerRank {
+            players: state.players,
+            questions: state.questions,
+            stage: state.stage,
+            minimum_set_reached: state.minimum_set_reached,
+            questioning_started: state.questioning_started,
+            min_set_question_queue: state.min_set_question_queue,
+            current_question: state.current_question,
+            skipped_questions: state.skipped_questions.into_iter().collect(),
+            answered_questions: state.answered_questions.into_iter().collect(),
+            minimum_linkage: state.minimum_linkage.into_iter().collect(),
+            rater: state.rater,
+            strategy: match state.strategy {
+                Some(strategy) => strategy.strategy(),
+                None => Box::new(FewestConnections),
+            },
+            rng: StdRng::seed_from_u64(state.seed),
+        })
+    }
+
+    // Change how the next question is picked. The built-in strategies are saved with the rest of
+    // the state, any others go back to FewestConnections in a restored session until they're set
+    // again
+    pub fn set_strategy(&mut self, strategy: Box<dyn QuestionStrategy>) {
+        self.strategy = strategy;
+    }
+
+    // Mark every answer given from now on as coming from rater
+    pub fn set_rater(&mut self, rater: Option<String>) {
+        self.rater = rater;
+    }
+
+    pub fn players(&self) -> &Players {
+        &self.players
+    }
+
+    // Every answered question, including ones from previous sessions, in the order they were given
+    pub fn questions(&self) -> &Questions {
+        &self.questions
+    }
+
+    // The question waiting for a response, if there is one
+    pub fn current_question(&self) -> Option<Question> {
+        self.current_question.as_ref().map(|q| self.to_question(q))
+    }
+
+    // Give back the players and answered questions, for saving them
+    pub fn into_parts(self) -> (Players, Questions) {
+        (self.players, self.questions)
     }
 }
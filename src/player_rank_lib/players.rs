@@ -1,19 +1,150 @@
-#[derive(Debug, PartialEq)]
+use crate::player_rank_lib::{Error, Position, PositionConfig, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Player {
+    // Questions refer to players by this, so it shouldn't change. The same as the name unless the
+    // player file has an id column
+    pub id: String,
     pub name: String,
-    pub goalie: bool,
-    pub avail1: bool,
-    pub avail2: bool,
+    // Other names the player has gone by, like old spellings of their name. Questions that use
+    // them still count for the player
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    // Every position the player can play: the ones anybody can, and the ones they're eligible for
+    pub positions: Vec<Position>,
+    // Whether the player can make each week, in the same order as Players::weeks
+    pub availability: Vec<bool>,
+    // Anything else known about the player, like their phone number, by column name
+    #[serde(default)]
+    pub metadata: BTreeMap<String, String>,
+}
+
+impl Player {
+    // Players only get compared in the positions they can play
+    pub fn can_play(&self, pos: &Position) -> bool {
+        self.positions.contains(pos)
+    }
+
+    // Whether the player can make a week, counting from 1. None if there's no such week
+    pub fn available(&self, week: usize) -> Option<bool> {
+        self.availability.get(week.checked_sub(1)?).copied()
+    }
 }
 
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Players {
     pub players: Vec<Player>,
+    // Names of the weeks players have said whether they can make, like "week1" or "2024-06-03"
+    #[serde(default)]
+    pub weeks: Vec<String>,
+    // The positions players get compared in
+    #[serde(default)]
+    pub position_config: PositionConfig,
 }
 
 impl Players {
     pub fn new() -> Self {
         Players {
             players: Vec::new(),
+            weeks: Vec::new(),
+            position_config: PositionConfig::default(),
+        }
+    }
+
+    pub fn with_positions(position_config: PositionConfig) -> Self {
+        Players {
+            players: Vec::new(),
+            weeks: Vec::new(),
+            position_config,
+        }
+    }
+
+    // Find the player a question refers to, by id, then by name, then by alias
+    pub fn find(&self, reference: &str) -> Option<&Player> {
+        self.players
+            .iter()
+            .find(|player| player.id == reference)
+            .or_else(|| self.players.iter().find(|player| player.name == reference))
+            .or_else(|| {
+                self.players
+                    .iter()
+                    .find(|player| player.aliases.iter().any(|alias| alias == reference))
+            })
+    }
+
+    // Like find, but not finding anyone is an UnknownPlayer error
+    pub fn get(&self, reference: &str) -> Result<&Player> {
+        self.find(reference)
+            .ok_or_else(|| Error::UnknownPlayer(reference.to_string()))
+    }
+
+    // Where the player a question refers to is in the list
+    pub fn index_of(&self, reference: &str) -> Result<usize> {
+        let id = &self.get(reference)?.id;
+        Ok(self
+            .players
+            .iter()
+            .position(|player| &player.id == id)
+            .expect("The player was just found"))
+    }
+
+    // The name to show for a player a question refers to. Unknown players keep the reference
+    pub fn display_name<'a>(&'a self, reference: &'a str) -> &'a str {
+        match self.find(reference) {
+            Some(player) => &player.name,
+            None => reference,
+        }
+    }
+
+    // Make sure every id is different, and that no name or alias could mean two players
+    pub fn check_references(&self) -> Result<()> {
+        let mut ids = HashMap::new();
+        for player in &self.players {
+            if ids.insert(player.id.as_str(), player).is_some() {
+                return Err(Error::DuplicateId(player.id.clone()));
+            }
         }
+        let mut owners: HashMap<&str, &str> = HashMap::new();
+        for player in &self.players {
+            let references = std::iter::once(&player.name).chain(&player.aliases);
+            for reference in references {
+                // Referring to someone by their id always means them
+                if ids.contains_key(reference.as_str()) && reference != &player.id {
+                    return Err(Error::AmbiguousReference {
+                        reference: reference.clone(),
+                        players: (reference.clone(), player.id.clone()),
+                    });
+                }
+                match owners.insert(reference, &player.id) {
+                    Some(other) if other != player.id => {
+                        return Err(Error::AmbiguousReference {
+                            reference: reference.clone(),
+                            players: (other.to_string(), player.id.clone()),
+                        })
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Find a week by its number, counting from 1, or by its name
+    pub fn week_number(&self, week: &str) -> Option<usize> {
+        if let Ok(number) = week.parse::<usize>() {
+            return (1..=self.weeks.len()).contains(&number).then_some(number);
+        }
+        self.weeks
+            .iter()
+            .position(|name| name == week)
+            .map(|i| i + 1)
+    }
+}
+
+impl Default for Players {
+    fn default() -> Self {
+        Self::new()
     }
 }
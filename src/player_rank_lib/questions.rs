@@ -1,31 +1,125 @@
-#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
-pub enum Position {
-    Atk,
-    Def,
-    Goalie,
-}
+use crate::player_rank_lib::{Error, Players};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::sync::Arc;
+
+/// A position players get compared in, like attack or goalie. Which positions there are comes
+/// from the position config, see PositionConfig
+#[derive(PartialEq, Eq, Hash, Clone, PartialOrd, Ord)]
+pub struct Position(Arc<str>);
 
 impl Position {
-    // Try to create a position from a string
-    pub fn from_str(s: &str) -> Option<Self> {
-        match s {
-            "Atk" => Some(Position::Atk),
-            "Def" => Some(Position::Def),
-            "Goalie" => Some(Position::Goalie),
-            _ => None, // Handle unrecognized strings
-        }
+    pub fn new(name: &str) -> Self {
+        Position(Arc::from(name))
+    }
+
+    pub fn name(&self) -> &str {
+        &self.0
     }
 
     pub fn to_str(&self) -> String {
-        match self {
-            Position::Atk => String::from("Atk"),
-            Position::Def => String::from("Def"),
-            Position::Goalie => String::from("Goalie"),
+        String::from(self.name())
+    }
+}
+
+// Try to create a position from a string. Any name will do, whether it's a position in the
+// config is up to whoever reads it
+impl std::str::FromStr for Position {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.trim();
+        if name.is_empty() {
+            return Err(());
+        }
+        Ok(Position::new(name))
+    }
+}
+
+impl std::fmt::Debug for Position {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl std::fmt::Display for Position {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl Serialize for Position {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.name())
+    }
+}
+
+impl<'de> Deserialize<'de> for Position {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        name.parse()
+            .map_err(|()| serde::de::Error::custom("position can't be empty"))
+    }
+}
+
+/// A position in the position config
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PositionInfo {
+    pub position: Position,
+    // Only players marked as able to play the position get compared in it, like goalies. The
+    // player file says who can with a column named after the position in lower case
+    #[serde(default)]
+    pub needs_eligibility: bool,
+}
+
+/// The positions of a sport, in the order their stages get asked
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PositionConfig {
+    pub positions: Vec<PositionInfo>,
+}
+
+impl PositionConfig {
+    // Every position, in stage order
+    pub fn list(&self) -> Vec<Position> {
+        self.positions
+            .iter()
+            .map(|info| info.position.clone())
+            .collect()
+    }
+
+    pub fn contains(&self, pos: &Position) -> bool {
+        self.positions.iter().any(|info| &info.position == pos)
+    }
+
+    pub fn needs_eligibility(&self, pos: &Position) -> bool {
+        self.positions
+            .iter()
+            .any(|info| &info.position == pos && info.needs_eligibility)
+    }
+
+    // The position whose stage comes after pos, if there is one
+    pub fn after(&self, pos: &Position) -> Option<Position> {
+        let i = self
+            .positions
+            .iter()
+            .position(|info| &info.position == pos)?;
+        self.positions.get(i + 1).map(|info| info.position.clone())
+    }
+}
+
+// Attack and defense for everyone, and goalie for those who can play in goal
+impl Default for PositionConfig {
+    fn default() -> Self {
+        let info = |name, needs_eligibility| PositionInfo {
+            position: Position::new(name),
+            needs_eligibility,
+        };
+        PositionConfig {
+            positions: vec![info("Atk", false), info("Def", false), info("Goalie", true)],
         }
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Question {
     pub player1: String,
     pub pos1: Position,
@@ -33,21 +127,99 @@ pub struct Question {
     pub pos2: Position,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct AnsweredQuestion {
     pub question: Question,
     pub response: f64,
+    // Who gave the answer, if we know
+    #[serde(default)]
+    pub rater: Option<String>,
+    // How much the answer counts compared to others when ranking. Answers from a rater who knows
+    // the players better can be given more weight
+    #[serde(default = "default_weight")]
+    pub weight: f64,
+}
+
+fn default_weight() -> f64 {
+    1.0
+}
+
+impl AnsweredQuestion {
+    // An answer from an unknown rater, with the usual weight
+    pub fn new(question: Question, response: f64) -> Self {
+        AnsweredQuestion {
+            question,
+            response,
+            rater: None,
+            weight: default_weight(),
+        }
+    }
 }
 
 /// Player Rank Interface: Input/output, questions list
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Questions {
     pub questions: Vec<AnsweredQuestion>,
+    // Questions the rater didn't want to answer, which shouldn't be asked again
+    #[serde(default)]
+    pub skipped: Vec<Question>,
 }
 
 impl Questions {
     pub fn new() -> Self {
         Questions {
             questions: Vec::new(),
+            skipped: Vec::new(),
+        }
+    }
+
+    // Add the answers from another set of questions, for combining several raters' answers into
+    // one set. Questions skipped in both only get added once
+    pub fn merge(&mut self, other: Questions) {
+        self.questions.extend(other.questions);
+        for question in other.skipped {
+            if !self.skipped.contains(&question) {
+                self.skipped.push(question);
+            }
+        }
+    }
+
+    // Point every question at the ids of the players it's about, for questions that use their
+    // names or aliases. Returns an UnknownPlayer error for each reference that doesn't match
+    // anyone, which is left as it is
+    pub fn resolve_players(&mut self, players: &Players) -> Vec<Error> {
+        let mut unknown = Vec::new();
+        let mut resolve = |reference: &mut String| match players.get(reference) {
+            Ok(player) => *reference = player.id.clone(),
+            Err(err) => {
+                if !unknown.contains(&err) {
+                    unknown.push(err);
+                }
+            }
+        };
+        let answered = self
+            .questions
+            .iter_mut()
+            .map(|answered| &mut answered.question);
+        for question in answered.chain(self.skipped.iter_mut()) {
+            resolve(&mut question.player1);
+            resolve(&mut question.player2);
+        }
+        unknown
+    }
+
+    // Change how much every answer from a rater counts
+    pub fn set_rater_weight(&mut self, rater: &str, weight: f64) {
+        for answered in &mut self.questions {
+            if answered.rater.as_deref() == Some(rater) {
+                answered.weight = weight;
+            }
         }
     }
 }
+
+impl Default for Questions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,8 +1,43 @@
+use crate::player_rank_lib::{Player, Position};
+use std::collections::HashMap;
+
 pub struct Rank {
+    pub id: String,
     pub name: String,
-    pub atk: f64,
-    pub def: f64,
-    pub goalie: Option<f64>,
+    // Skill in each position the player can play. Positions that need eligibility, like goalie,
+    // only have one once the player has been compared there
+    pub skills: HashMap<Position, f64>,
+    // Standard errors of the log of each skill. Missing if the player hasn't been compared in
+    // that position, so there's nothing to say about how good the estimate is
+    pub std_errs: HashMap<Position, f64>,
+}
+
+impl Rank {
+    // A player without any skills yet
+    pub fn new(player: &Player) -> Self {
+        Rank {
+            id: player.id.clone(),
+            name: player.name.clone(),
+            skills: HashMap::new(),
+            std_errs: HashMap::new(),
+        }
+    }
+
+    // The player's skill in a position, if they have one
+    pub fn skill(&self, pos: &Position) -> Option<f64> {
+        self.skills.get(pos).copied()
+    }
+
+    // The standard error of the log of the player's skill in a position, if it's known
+    pub fn std_err(&self, pos: &Position) -> Option<f64> {
+        self.std_errs.get(pos).copied()
+    }
+
+    // The range a skill falls in 95% of the time, given its log standard error
+    pub fn confidence_interval(skill: f64, std_err: f64) -> (f64, f64) {
+        let spread = (1.96 * std_err).exp();
+        (skill / spread, skill * spread)
+    }
 }
 
 pub struct Ranks {
@@ -14,3 +49,9 @@ impl Ranks {
         Ranks { ranks: Vec::new() }
     }
 }
+
+impl Default for Ranks {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,444 @@
+use crate::player_rank_lib::*;
+use std::collections::HashMap;
+
+// Every answered question says skill(player1, pos1) / skill(player2, pos2) = skill_factor.
+// Taking the log turns each answer into a linear equation, x1 - x2 = ln(skill_factor),
+// and with more answers than players the system is over-determined, so we find the
+// least-squares solution.
+
+/// Turns answered questions into a ranking of every player. Every answer says
+/// skill(player1, pos1) / skill(player2, pos2) = skill_factor, and a solver finds the skills that
+/// best fit all of them
+pub trait RankingSolver {
+    fn rank(&self, players: &Players, questions: &Questions) -> Result<Ranks>;
+}
+
+// The skills a solver found for every player in a single position
+pub(crate) struct PositionSkills {
+    pub log_skills: Vec<f64>,
+    // Standard error of each log-skill. Solvers that can't tell how certain their skills are,
+    // like Eigenvector and BradleyTerry, leave these all None
+    pub std_errs: Vec<Option<f64>>,
+    // Number of comparisons each player was part of
+    pub degree: Vec<usize>,
+}
+
+// A single answered comparison between two players, in log space
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Comparison {
+    pub lhs: usize,
+    pub rhs: usize,
+    pub log_ratio: f64,
+    // How much the answer counts
+    pub weight: f64,
+    // Where the answer is in the question list
+    pub index: usize,
+}
+
+// The least-squares log-skill of every player for a single position
+pub(crate) struct PositionSolution {
+    pub log_skills: Vec<f64>,
+    // Standard error of each log-skill, None for players without any comparisons
+    pub std_errs: Vec<Option<f64>>,
+    // Number of comparisons each player was part of
+    pub degree: Vec<usize>,
+    // Pseudo-inverse of the comparison graph's laplacian
+    pub pseudo_inverse: Vec<Vec<f64>>,
+    pub residual_sum_sq: f64,
+    // How many more answers there are than needed to link every connected component
+    pub redundant_answers: f64,
+}
+
+impl PositionSolution {
+    // Estimate the variance of a single answer, optionally pretending some answers weren't given
+    pub fn answer_variance(&self, removed_sum_sq: f64, removed_answers: f64) -> f64 {
+        (self.residual_sum_sq - removed_sum_sq + PRIOR_WEIGHT * PRIOR_LOG_STD_DEV.powi(2))
+            / (self.redundant_answers - removed_answers + PRIOR_WEIGHT)
+    }
+
+    // How far off the comparison's answer is expected to be, compared to the average answer.
+    // Answers with more weight are assumed to be closer to the truth
+    pub fn leverage(&self, comparison: &Comparison) -> f64 {
+        let (i, j) = (comparison.lhs, comparison.rhs);
+        let inverse = &self.pseudo_inverse;
+        comparison.weight * (inverse[i][i] + inverse[j][j] - 2.0 * inverse[i][j])
+    }
+
+    // The comparison's answer minus what the solution says it should've been
+    pub fn residual(&self, comparison: &Comparison) -> f64 {
+        comparison.log_ratio - (self.log_skills[comparison.lhs] - self.log_skills[comparison.rhs])
+    }
+}
+
+// How far off we assume an answer is (in log space) before there are enough answers to tell.
+// ln(1.25) means a typical answer is within 25% of the truth
+const PRIOR_LOG_STD_DEV: f64 = 0.223;
+// How many answers that assumption is worth when combined with the measured consistency
+const PRIOR_WEIGHT: f64 = 1.0;
+
+fn player_indices(players: &Players) -> HashMap<&str, usize> {
+    players
+        .players
+        .iter()
+        .enumerate()
+        .map(|(i, player)| (player.id.as_str(), i))
+        .collect()
+}
+
+// If an answer is a ratio we can take the log of, and has any weight
+fn counts(answered: &AnsweredQuestion) -> bool {
+    answered.response.is_finite()
+        && answered.response > 0.0
+        && answered.weight.is_finite()
+        && answered.weight > 0.0
+}
+
+// Collect all the same-position comparisons for a position, referencing players by index
+pub(crate) fn position_comparisons(
+    players: &Players,
+    questions: &Questions,
+    pos: &Position,
+) -> Vec<Comparison> {
+    let index = player_indices(players);
+
+    let mut comparisons = Vec::new();
+    for (question_index, answered) in questions.questions.iter().enumerate() {
+        let question = &answered.question;
+        if question.pos1 != *pos || question.pos2 != *pos {
+            continue;
+        }
+
+        // Ignore anything we can't take the log of, or that doesn't reference two known players
+        if !counts(answered) {
+            continue;
+        }
+        let (lhs, rhs) = match (
+            index.get(question.player1.as_str()),
+            index.get(question.player2.as_str()),
+        ) {
+            (Some(lhs), Some(rhs)) if lhs != rhs => (*lhs, *rhs),
+            _ => continue,
+        };
+
+        // Comparisons of players who can't play the position don't mean anything
+        if !players.players[lhs].can_play(pos) || !players.players[rhs].can_play(pos) {
+            continue;
+        }
+
+        comparisons.push(Comparison {
+            lhs,
+            rhs,
+            log_ratio: answered.response.ln(),
+            weight: answered.weight,
+            index: question_index,
+        });
+    }
+    comparisons
+}
+
+// Count how many comparisons each player is part of
+pub(crate) fn comparison_degrees(num_players: usize, comparisons: &[Comparison]) -> Vec<usize> {
+    let mut degree = vec![0; num_players];
+    for comparison in comparisons {
+        degree[comparison.lhs] += 1;
+        degree[comparison.rhs] += 1;
+    }
+    degree
+}
+
+// Shift the log-skills of every connected component so each one has a geometric mean of 1
+pub(crate) fn center_components(log_skills: &mut [f64], component: &[usize]) {
+    let mut totals: HashMap<usize, (f64, usize)> = HashMap::new();
+    for (log_skill, c) in log_skills.iter().zip(component) {
+        let total = totals.entry(*c).or_insert((0.0, 0));
+        total.0 += log_skill;
+        total.1 += 1;
+    }
+    for (log_skill, c) in log_skills.iter_mut().zip(component) {
+        let (sum, count) = totals[c];
+        *log_skill -= sum / count as f64;
+    }
+}
+
+// Label each player with the connected component of the comparison graph they belong to
+pub(crate) fn connected_components(num_players: usize, comparisons: &[Comparison]) -> Vec<usize> {
+    let mut component: Vec<Option<usize>> = vec![None; num_players];
+    let mut neighbours: Vec<Vec<usize>> = vec![Vec::new(); num_players];
+    for comparison in comparisons {
+        neighbours[comparison.lhs].push(comparison.rhs);
+        neighbours[comparison.rhs].push(comparison.lhs);
+    }
+
+    let mut num_components = 0;
+    for start in 0..num_players {
+        if component[start].is_some() {
+            continue;
+        }
+        // Flood fill from this player
+        let mut stack = vec![start];
+        component[start] = Some(num_components);
+        while let Some(player) = stack.pop() {
+            for &next in &neighbours[player] {
+                if component[next].is_none() {
+                    component[next] = Some(num_components);
+                    stack.push(next);
+                }
+            }
+        }
+        num_components += 1;
+    }
+
+    component.into_iter().flatten().collect()
+}
+
+// Invert a with gauss-jordan elimination and partial pivoting. Returns None if a is singular
+fn invert_matrix(mut a: Vec<Vec<f64>>) -> Option<Vec<Vec<f64>>> {
+    let n = a.len();
+    let mut inverse: Vec<Vec<f64>> = (0..n)
+        .map(|i| (0..n).map(|j| if i == j { 1.0 } else { 0.0 }).collect())
+        .collect();
+
+    for col in 0..n {
+        // Pivot on the largest remaining value in this column
+        let pivot = (col..n).max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))?;
+        if a[pivot][col].abs() < 1e-12 {
+            return None;
+        }
+        a.swap(col, pivot);
+        inverse.swap(col, pivot);
+
+        // Scale the pivot row so the pivot is 1
+        let scale = a[col][col];
+        a[col].iter_mut().for_each(|value| *value /= scale);
+        inverse[col].iter_mut().for_each(|value| *value /= scale);
+
+        // Eliminate this column from every other row
+        let (pivot_row, pivot_inverse_row) = (a[col].clone(), inverse[col].clone());
+        for row in 0..n {
+            let factor = a[row][col];
+            if row == col || factor == 0.0 {
+                continue;
+            }
+            for (value, pivot_value) in a[row].iter_mut().zip(&pivot_row) {
+                *value -= factor * pivot_value;
+            }
+            for (value, pivot_value) in inverse[row].iter_mut().zip(&pivot_inverse_row) {
+                *value -= factor * pivot_value;
+            }
+        }
+    }
+    Some(inverse)
+}
+
+// Find the least-squares log-skills for a set of comparisons.
+//
+// Each answer counts as if it had been given weight times. The normal equations are L x = b,
+// where L is the weighted laplacian of the comparison graph. L is singular since adding a
+// constant to every player in a connected component doesn't change any ratio, so we pin the
+// geometric mean of every component to 1 (mean log-skill of 0) by adding 1/n to every entry of
+// the component's block. That makes the system solvable without changing the solution.
+//
+// The answers' consistency comes from the residuals of the fit. Scaling the inverse of the
+// system by the residual variance gives the covariance of the log-skills, whose diagonal is the
+// squared standard error of each player, relative to the geometric mean of their component.
+pub(crate) fn solve_comparisons(
+    num_players: usize,
+    comparisons: &[Comparison],
+) -> Result<PositionSolution> {
+    let mut laplacian = vec![vec![0.0; num_players]; num_players];
+    let mut rhs = vec![0.0; num_players];
+    for comparison in comparisons {
+        let (i, j, weight) = (comparison.lhs, comparison.rhs, comparison.weight);
+        laplacian[i][i] += weight;
+        laplacian[j][j] += weight;
+        laplacian[i][j] -= weight;
+        laplacian[j][i] -= weight;
+        rhs[i] += weight * comparison.log_ratio;
+        rhs[j] -= weight * comparison.log_ratio;
+    }
+
+    let component = connected_components(num_players, comparisons);
+    let mut component_size: HashMap<usize, usize> = HashMap::new();
+    for c in &component {
+        *component_size.entry(*c).or_insert(0) += 1;
+    }
+    for i in 0..num_players {
+        for j in 0..num_players {
+            if component[i] == component[j] {
+                laplacian[i][j] += 1.0 / component_size[&component[i]] as f64;
+            }
+        }
+    }
+
+    let mut inverse = invert_matrix(laplacian).ok_or(Error::Unsolvable)?;
+    let log_skills: Vec<f64> = inverse
+        .iter()
+        .map(|row| row.iter().zip(&rhs).map(|(a, b)| a * b).sum())
+        .collect();
+
+    // Remove the 1/n we added to get the pseudo-inverse back
+    for i in 0..num_players {
+        for j in 0..num_players {
+            if component[i] == component[j] {
+                inverse[i][j] -= 1.0 / component_size[&component[i]] as f64;
+            }
+        }
+    }
+
+    let mut solution = PositionSolution {
+        log_skills,
+        std_errs: Vec::new(),
+        degree: comparison_degrees(num_players, comparisons),
+        pseudo_inverse: inverse,
+        residual_sum_sq: 0.0,
+        // Every connected component needs one less answer than it has players before there's any
+        // redundancy to measure
+        redundant_answers: (comparisons.len() + component_size.len()) as f64 - num_players as f64,
+    };
+
+    // Estimate the answer variance from the residuals
+    solution.residual_sum_sq = comparisons
+        .iter()
+        .map(|c| c.weight * solution.residual(c).powi(2))
+        .sum();
+    let variance = solution.answer_variance(0.0, 0.0);
+
+    solution.std_errs = (0..num_players)
+        .map(|i| {
+            if solution.degree[i] == 0 {
+                return None;
+            }
+            let diagonal = solution.pseudo_inverse[i][i];
+            Some((variance * diagonal.max(0.0)).sqrt())
+        })
+        .collect();
+
+    Ok(solution)
+}
+
+// Each position is solved on its own scale, so "atk 1.3" and "def 1.3" don't mean the same thing.
+// Self ratings compare one player in two positions, which tells us how the scales line up: if
+// skill(p, pos1) / skill(p, pos2) = r, the offsets between the scales must satisfy
+// offset1 - offset2 = ln(r) - (x(p, pos1) - x(p, pos2)). That's the same kind of system as the
+// player comparisons, with positions in place of players, so solve it the same way and shift
+// each position's skills by its offset. Positions not linked by any self ratings stay as they are
+fn calibrate_positions(
+    players: &Players,
+    questions: &Questions,
+    solutions: &mut HashMap<Position, PositionSkills>,
+) -> Result<()> {
+    let index = player_indices(players);
+    let positions = players.position_config.list();
+    let pos_index = |pos: &Position| positions.iter().position(|p| p == pos);
+
+    let mut comparisons = Vec::new();
+    for (question_index, answered) in questions.questions.iter().enumerate() {
+        let question = &answered.question;
+        if Stage::of_question(question) != Some(Stage::SelfRating) {
+            continue;
+        }
+        if !counts(answered) {
+            continue;
+        }
+        let (player, lhs, rhs) = match (
+            index.get(question.player1.as_str()),
+            pos_index(&question.pos1),
+            pos_index(&question.pos2),
+        ) {
+            (Some(player), Some(lhs), Some(rhs)) => (*player, lhs, rhs),
+            _ => continue,
+        };
+
+        // A player who hasn't been compared in a position isn't on that position's scale yet
+        let (skills1, skills2) = (&solutions[&question.pos1], &solutions[&question.pos2]);
+        if skills1.degree[player] == 0 || skills2.degree[player] == 0 {
+            continue;
+        }
+
+        comparisons.push(Comparison {
+            lhs,
+            rhs,
+            log_ratio: answered.response.ln()
+                - (skills1.log_skills[player] - skills2.log_skills[player]),
+            weight: answered.weight,
+            index: question_index,
+        });
+    }
+
+    let offsets = solve_comparisons(positions.len(), &comparisons)?;
+    for (i, pos) in positions.iter().enumerate() {
+        let skills = solutions
+            .get_mut(pos)
+            .expect("Every position has been solved");
+        for log_skill in skills.log_skills.iter_mut() {
+            *log_skill += offsets.log_skills[i];
+        }
+        // The offset is uncertain too
+        if let Some(offset_err) = offsets.std_errs[i] {
+            for std_err in skills.std_errs.iter_mut().flatten() {
+                *std_err = std_err.hypot(offset_err);
+            }
+        }
+    }
+    Ok(())
+}
+
+// Solve every position separately with solve_position, line the positions up on a common scale,
+// and put the results together into ranks
+pub(crate) fn rank_by_position<F>(
+    players: &Players,
+    questions: &Questions,
+    mut solve_position: F,
+) -> Result<Ranks>
+where
+    F: FnMut(usize, &[Comparison]) -> Result<PositionSkills>,
+{
+    let num_players = players.players.len();
+    let mut solutions = HashMap::new();
+    let config = &players.position_config;
+    for pos in config.list() {
+        let comparisons = position_comparisons(players, questions, &pos);
+        solutions.insert(pos, solve_position(num_players, &comparisons)?);
+    }
+    calibrate_positions(players, questions, &mut solutions)?;
+
+    let mut ranks = Ranks::new();
+    for (i, player) in players.players.iter().enumerate() {
+        let mut rank = Rank::new(player);
+        for pos in config.list() {
+            let solution = &solutions[&pos];
+            // Only players who have been compared in a position that needs eligibility, like
+            // goalies in goal, have a rating there
+            if !player.can_play(&pos) || (config.needs_eligibility(&pos) && solution.degree[i] == 0)
+            {
+                continue;
+            }
+            rank.skills
+                .insert(pos.clone(), solution.log_skills[i].exp());
+            if let Some(std_err) = solution.std_errs[i] {
+                rank.std_errs.insert(pos, std_err);
+            }
+        }
+        ranks.ranks.push(rank);
+    }
+    Ok(ranks)
+}
+
+/// Rank every player at every position with a least-squares fit of the answered questions in
+/// log space. Within each position skills are scaled so their geometric mean is 1, and players
+/// without any comparisons get a skill of 1. Self ratings then shift whole positions onto a
+/// common scale
+pub struct LogLeastSquares;
+
+impl RankingSolver for LogLeastSquares {
+    fn rank(&self, players: &Players, questions: &Questions) -> Result<Ranks> {
+        rank_by_position(players, questions, |num_players, comparisons| {
+            let solution = solve_comparisons(num_players, comparisons)?;
+            Ok(PositionSkills {
+                log_skills: solution.log_skills,
+                std_errs: solution.std_errs,
+                degree: solution.degree,
+            })
+        })
+    }
+}
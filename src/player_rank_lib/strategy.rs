@@ -0,0 +1,205 @@
+use crate::player_rank_lib::solver::{self, PositionSolution};
+use crate::player_rank_lib::*;
+use rand::seq::SliceRandom;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// What a question strategy gets to see when picking the next question
+pub struct QuestionContext<'a> {
+    pub players: &'a Players,
+    // Every answered and skipped question so far
+    pub questions: &'a Questions,
+    pub stage: Stage,
+    // Until the minimum set of questions has been answered, the candidates are the questions
+    // still needed to link everyone up in the stage, and they all get asked eventually.
+    // Afterwards they're every question in the stage that hasn't been answered or skipped, in a
+    // random order
+    pub minimum_set_reached: bool,
+    pub candidates: &'a [Question],
+}
+
+/// Decides which question gets asked next. PlayerRank works out which questions are worth asking
+/// in each stage, and the strategy picks one of them
+pub trait QuestionStrategy: Send {
+    // The index into context.candidates of the question to ask next. There's always at least one
+    // candidate
+    fn choose(&mut self, context: &QuestionContext, rng: &mut dyn RngCore) -> usize;
+
+    // The strategy as it gets saved with the rest of a session. Strategies that can't be saved
+    // leave it out, and a restored session goes back to FewestConnections
+    fn save(&self) -> Option<SavedStrategy> {
+        None
+    }
+}
+
+/// One of the built-in strategies, as it's saved with a session so a restored session carries on
+/// picking questions the same way
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SavedStrategy {
+    FewestConnections,
+    MostInformative,
+    RandomOrder,
+    RoundRobin { next_player: usize },
+}
+
+impl SavedStrategy {
+    pub fn strategy(&self) -> Box<dyn QuestionStrategy> {
+        match *self {
+            SavedStrategy::FewestConnections => Box::new(FewestConnections),
+            SavedStrategy::MostInformative => Box::new(MostInformative),
+            SavedStrategy::RandomOrder => Box::new(RandomOrder),
+            SavedStrategy::RoundRobin { next_player } => Box::new(RoundRobin { next_player }),
+        }
+    }
+}
+
+// Count how many answered questions in the stage each player is part of
+fn answers_per_player<'a>(context: &QuestionContext<'a>) -> HashMap<&'a str, usize> {
+    let mut answers = HashMap::new();
+    for answered in &context.questions.questions {
+        let question = &answered.question;
+        if Stage::of_question(question).as_ref() != Some(&context.stage) {
+            continue;
+        }
+        *answers.entry(question.player1.as_str()).or_insert(0) += 1;
+        if question.player2 != question.player1 {
+            *answers.entry(question.player2.as_str()).or_insert(0) += 1;
+        }
+    }
+    answers
+}
+
+/// The original ordering: the questions linking everyone up in the order they were drawn, then
+/// whichever question compares the players with the fewest answers so far
+pub struct FewestConnections;
+
+impl QuestionStrategy for FewestConnections {
+    fn choose(&mut self, context: &QuestionContext, _rng: &mut dyn RngCore) -> usize {
+        if !context.minimum_set_reached {
+            return context.candidates.len() - 1;
+        }
+
+        let answers = answers_per_player(context);
+        let count = |player: &str| answers.get(player).cloned().unwrap_or(0);
+        let mut best = 0;
+        let mut best_links = usize::MAX;
+        for (i, question) in context.candidates.iter().enumerate() {
+            let links = (count(&question.player1) + count(&question.player2)) / 2;
+            if links < best_links {
+                best = i;
+                best_links = links;
+            }
+        }
+        best
+    }
+
+    fn save(&self) -> Option<SavedStrategy> {
+        Some(SavedStrategy::FewestConnections)
+    }
+}
+
+// How much comparing players i and j would take off the total variance of the log-skills
+fn variance_reduction(solution: &PositionSolution, component: &[usize], i: usize, j: usize) -> f64 {
+    if component[i] != component[j] {
+        return f64::INFINITY;
+    }
+    let inverse = &solution.pseudo_inverse;
+    let column: Vec<f64> = inverse.iter().map(|row| row[i] - row[j]).collect();
+    let leverage = column[i] - column[j];
+    column.iter().map(|value| value * value).sum::<f64>() / (1.0 + leverage)
+}
+
+/// Once the minimum set has been answered, ask the position comparison that would shrink the
+/// uncertainty of the ranking the most. Adding a comparison between players i and j to a
+/// laplacian with pseudo-inverse P takes |P (e_i - e_j)|^2 / (1 + leverage) off the trace of the
+/// covariance, by the Sherman-Morrison formula. Comparisons that link two groups of players who
+/// haven't been compared yet beat everything else. Everything else goes by fewest connections
+pub struct MostInformative;
+
+impl QuestionStrategy for MostInformative {
+    fn choose(&mut self, context: &QuestionContext, rng: &mut dyn RngCore) -> usize {
+        let pos = match &context.stage {
+            Stage::Position(pos) if context.minimum_set_reached => pos,
+            _ => return FewestConnections.choose(context, rng),
+        };
+        let num_players = context.players.players.len();
+        let comparisons = solver::position_comparisons(context.players, context.questions, pos);
+        let solution = match solver::solve_comparisons(num_players, &comparisons) {
+            Ok(solution) => solution,
+            Err(_) => return FewestConnections.choose(context, rng),
+        };
+        let component = solver::connected_components(num_players, &comparisons);
+        let index = |id: &str| context.players.players.iter().position(|p| p.id == id);
+
+        let mut best: Option<(usize, f64)> = None;
+        for (i, question) in context.candidates.iter().enumerate() {
+            let (player1, player2) = match (index(&question.player1), index(&question.player2)) {
+                (Some(player1), Some(player2)) => (player1, player2),
+                _ => continue,
+            };
+            let reduction = variance_reduction(&solution, &component, player1, player2);
+            if best.is_none_or(|(_, best_reduction)| reduction > best_reduction) {
+                best = Some((i, reduction));
+            }
+        }
+        best.map_or(0, |(i, _)| i)
+    }
+
+    fn save(&self) -> Option<SavedStrategy> {
+        Some(SavedStrategy::MostInformative)
+    }
+}
+
+/// Ask the questions in a random order
+pub struct RandomOrder;
+
+impl QuestionStrategy for RandomOrder {
+    fn choose(&mut self, context: &QuestionContext, rng: &mut dyn RngCore) -> usize {
+        let indices: Vec<usize> = (0..context.candidates.len()).collect();
+        *indices.choose(rng).expect("There's always a candidate")
+    }
+
+    fn save(&self) -> Option<SavedStrategy> {
+        Some(SavedStrategy::RandomOrder)
+    }
+}
+
+/// Take turns going through the player list, asking a question about each player in turn, so
+/// nobody waits long to be compared
+#[derive(Default)]
+pub struct RoundRobin {
+    // The player whose turn is next
+    next_player: usize,
+}
+
+impl RoundRobin {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl QuestionStrategy for RoundRobin {
+    fn choose(&mut self, context: &QuestionContext, _rng: &mut dyn RngCore) -> usize {
+        let players = &context.players.players;
+        for turn in 0..players.len() {
+            let player = (self.next_player + turn) % players.len();
+            let id = &players[player].id;
+            if let Some(i) = context
+                .candidates
+                .iter()
+                .position(|question| &question.player1 == id || &question.player2 == id)
+            {
+                self.next_player = (player + 1) % players.len();
+                return i;
+            }
+        }
+        0
+    }
+
+    fn save(&self) -> Option<SavedStrategy> {
+        Some(SavedStrategy::RoundRobin {
+            next_player: self.next_player,
+        })
+    }
+}
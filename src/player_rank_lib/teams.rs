@@ -0,0 +1,256 @@
+use crate::player_rank_lib::*;
+use std::collections::HashMap;
+
+#[derive(Debug)]
+pub struct Team {
+    // The player in each position that needs eligibility, like the goalie, if there was anyone
+    // left to play it
+    pub specialists: HashMap<Position, String>,
+    // Everyone else on the team
+    pub players: Vec<String>,
+    // Total skill of the players in each position anyone can play, not counting the specialists,
+    // and the skill of each specialist in their position
+    pub skills: HashMap<Position, f64>,
+}
+
+/// Teams for a week, along with how far apart they are
+#[derive(Debug)]
+pub struct Teams {
+    pub teams: Vec<Team>,
+    // Difference between the strongest and weakest team in each position
+    pub spreads: HashMap<Position, f64>,
+}
+
+// A player available this week, with the skills that matter for building teams
+struct Member {
+    name: String,
+    skills: HashMap<Position, f64>,
+}
+
+impl Member {
+    // Players that haven't been compared in a position are average
+    fn skill(&self, pos: &Position) -> f64 {
+        self.skills.get(pos).copied().unwrap_or(1.0)
+    }
+}
+
+// Who's on each team. Field players play every position anyone can play, and each position that
+// needs eligibility has a slot per team for one specialist
+struct Lineup {
+    field: Vec<Vec<usize>>,
+    specialists: Vec<(Position, Vec<Option<usize>>)>,
+}
+
+// Difference between the largest and smallest team totals of a skill
+fn spread<F: Fn(&[usize]) -> f64>(teams: &[Vec<usize>], total: F) -> f64 {
+    let totals: Vec<f64> = teams.iter().map(|team| total(team)).collect();
+    let max = totals.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+    let min = totals.iter().cloned().fold(f64::INFINITY, f64::min);
+    max - min
+}
+
+// How unbalanced a set of teams is. Lower is better
+fn imbalance(members: &[Member], field_positions: &[Position], lineup: &Lineup) -> f64 {
+    let field: f64 = field_positions
+        .iter()
+        .map(|pos| {
+            spread(&lineup.field, |team| {
+                team.iter().map(|&m| members[m].skill(pos)).sum()
+            })
+        })
+        .sum();
+    let specialists: f64 = lineup
+        .specialists
+        .iter()
+        .map(|(pos, slots)| {
+            let slot_teams: Vec<Vec<usize>> =
+                slots.iter().map(|s| s.iter().cloned().collect()).collect();
+            spread(&slot_teams, |team| {
+                team.iter().map(|&m| members[m].skill(pos)).sum()
+            })
+        })
+        .sum();
+    field + specialists
+}
+
+// Keep swapping players between teams while it makes the teams more even
+fn improve_by_swapping(members: &[Member], field_positions: &[Position], lineup: &mut Lineup) {
+    let mut best = imbalance(members, field_positions, lineup);
+    let mut improved = true;
+    while improved {
+        improved = false;
+
+        for a in 0..lineup.field.len() {
+            for b in (a + 1)..lineup.field.len() {
+                // Swap specialists
+                for s in 0..lineup.specialists.len() {
+                    lineup.specialists[s].1.swap(a, b);
+                    let cost = imbalance(members, field_positions, lineup);
+                    if cost < best - 1e-12 {
+                        best = cost;
+                        improved = true;
+                    } else {
+                        lineup.specialists[s].1.swap(a, b);
+                    }
+                }
+
+                // Swap field players
+                for i in 0..lineup.field[a].len() {
+                    for j in 0..lineup.field[b].len() {
+                        let (player_a, player_b) = (lineup.field[a][i], lineup.field[b][j]);
+                        lineup.field[a][i] = player_b;
+                        lineup.field[b][j] = player_a;
+                        let cost = imbalance(members, field_positions, lineup);
+                        if cost < best - 1e-12 {
+                            best = cost;
+                            improved = true;
+                        } else {
+                            lineup.field[a][i] = player_a;
+                            lineup.field[b][j] = player_b;
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Split the players available in a week (counting from 1) into num_teams teams with as even
+/// strength in every position as we can find. Every team gets a player for each position that
+/// needs eligibility, like a goalie, if there are enough players who can play it, and anyone left
+/// over plays on the field
+pub fn build_teams(
+    players: &Players,
+    ranks: &Ranks,
+    week: usize,
+    num_teams: usize,
+) -> Result<Teams> {
+    if num_teams == 0 {
+        return Err(Error::NoTeams);
+    }
+    if !(1..=players.weeks.len()).contains(&week) {
+        return Err(Error::UnknownWeek(week));
+    }
+    let config = &players.position_config;
+    let (specialist_positions, field_positions): (Vec<Position>, Vec<Position>) = config
+        .list()
+        .into_iter()
+        .partition(|pos| config.needs_eligibility(pos));
+
+    // Gather everyone available with their skills
+    let mut members = Vec::new();
+    let mut eligible: Vec<Vec<usize>> = vec![Vec::new(); specialist_positions.len()];
+    for player in &players.players {
+        if player.available(week) != Some(true) {
+            continue;
+        }
+        let rank = ranks
+            .ranks
+            .iter()
+            .find(|rank| rank.id == player.id)
+            .ok_or_else(|| Error::NoRank(player.name.clone()))?;
+
+        for (s, pos) in specialist_positions.iter().enumerate() {
+            if player.can_play(pos) {
+                eligible[s].push(members.len());
+            }
+        }
+        members.push(Member {
+            name: player.name.clone(),
+            skills: rank.skills.clone(),
+        });
+    }
+    if members.len() < num_teams {
+        return Err(Error::TooFewPlayers {
+            needed: num_teams,
+            found: members.len(),
+        });
+    }
+
+    // The best players in each specialist position play it, one per team. Nobody plays two
+    let mut taken = vec![false; members.len()];
+    let mut specialists = Vec::new();
+    for (pos, mut candidates) in specialist_positions.iter().zip(eligible) {
+        candidates.retain(|&m| !taken[m]);
+        candidates.sort_by(|&a, &b| members[b].skill(pos).total_cmp(&members[a].skill(pos)));
+        let mut slots: Vec<Option<usize>> = vec![None; num_teams];
+        for (team, &member) in candidates.iter().take(num_teams).enumerate() {
+            slots[team] = Some(member);
+            taken[member] = true;
+        }
+        specialists.push((pos.clone(), slots));
+    }
+
+    // Snake draft everyone else, strongest first, filling the teams with the fewest specialists
+    // first
+    let strength = |m: usize| -> f64 {
+        field_positions
+            .iter()
+            .map(|pos| members[m].skill(pos))
+            .sum()
+    };
+    let mut field_players: Vec<usize> = (0..members.len()).filter(|&m| !taken[m]).collect();
+    field_players.sort_by(|&a, &b| strength(b).total_cmp(&strength(a)));
+    let mut draft_order: Vec<usize> = (0..num_teams).collect();
+    draft_order.sort_by_key(|&team| {
+        specialists
+            .iter()
+            .filter(|(_, slots)| slots[team].is_some())
+            .count()
+    });
+    let mut field: Vec<Vec<usize>> = vec![Vec::new(); num_teams];
+    for (pick, member) in field_players.into_iter().enumerate() {
+        let round = pick / num_teams;
+        let slot = pick % num_teams;
+        let slot = if round.is_multiple_of(2) {
+            slot
+        } else {
+            num_teams - 1 - slot
+        };
+        field[draft_order[slot]].push(member);
+    }
+
+    let mut lineup = Lineup { field, specialists };
+    improve_by_swapping(&members, &field_positions, &mut lineup);
+
+    let teams: Vec<Team> = (0..num_teams)
+        .map(|team| {
+            let field = &lineup.field[team];
+            // Fold from 0.0, summing no players at all gives -0.0, which prints as "-0.00"
+            let mut skills: HashMap<Position, f64> = field_positions
+                .iter()
+                .map(|pos| {
+                    let total = field
+                        .iter()
+                        .fold(0.0, |total, &m| total + members[m].skill(pos));
+                    (pos.clone(), total)
+                })
+                .collect();
+            let mut team_specialists = HashMap::new();
+            for (pos, slots) in &lineup.specialists {
+                if let Some(m) = slots[team] {
+                    team_specialists.insert(pos.clone(), members[m].name.clone());
+                    skills.insert(pos.clone(), members[m].skill(pos));
+                }
+            }
+            Team {
+                specialists: team_specialists,
+                players: field.iter().map(|&m| members[m].name.clone()).collect(),
+                skills,
+            }
+        })
+        .collect();
+
+    let spreads = config
+        .list()
+        .into_iter()
+        .map(|pos| {
+            let skill = |team: &Team| team.skills.get(&pos).copied().unwrap_or(0.0);
+            let max = teams.iter().map(skill).fold(f64::NEG_INFINITY, f64::max);
+            let min = teams.iter().map(skill).fold(f64::INFINITY, f64::min);
+            (pos, max - min)
+        })
+        .collect();
+
+    Ok(Teams { teams, spreads })
+}
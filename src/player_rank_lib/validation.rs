@@ -0,0 +1,152 @@
+use crate::player_rank_lib::*;
+use std::collections::HashMap;
+
+/// Which question in a set of questions something is about
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuestionRef {
+    Answered(usize),
+    Skipped(usize),
+}
+
+/// Something wrong with a question. Errors make the question meaningless, warnings are worth a
+/// look but the question can still be used or ignored safely
+#[derive(Debug, Clone, PartialEq)]
+pub enum QuestionProblem {
+    // Error: the position isn't in the position config
+    UnknownPosition(Position),
+    // Error: a player compared with themselves in the same position
+    SelfComparison,
+    // Error: two different players in two different positions, which no stage asks
+    MixedPositions,
+    // Error: the skill factor has to be a positive number
+    InvalidResponse(f64),
+    // Error: the weight can't be negative
+    InvalidWeight(f64),
+    // Warning: a player who isn't in the player file, so the question gets ignored
+    UnknownPlayer(String),
+    // Warning: a player compared in a position they can't play, so the question gets ignored
+    CantPlay(String, Position),
+    // Warning: the same question answered again by the same rater, or skipped again
+    Duplicate(QuestionRef),
+}
+
+impl QuestionProblem {
+    pub fn is_error(&self) -> bool {
+        !matches!(
+            self,
+            QuestionProblem::UnknownPlayer(_)
+                | QuestionProblem::CantPlay(_, _)
+                | QuestionProblem::Duplicate(_)
+        )
+    }
+}
+
+/// A problem with one question
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuestionIssue {
+    pub question: QuestionRef,
+    pub problem: QuestionProblem,
+}
+
+// The same question asked either way round has the same key
+type QuestionKey<'a> = [(&'a str, &'a Position); 2];
+
+fn question_key(question: &Question) -> QuestionKey<'_> {
+    let mut sides = [
+        (question.player1.as_str(), &question.pos1),
+        (question.player2.as_str(), &question.pos2),
+    ];
+    sides.sort();
+    sides
+}
+
+// Problems with a question that don't depend on any other question
+fn check_question(players: &Players, question: &Question) -> Vec<QuestionProblem> {
+    let mut problems = Vec::new();
+    for pos in [&question.pos1, &question.pos2] {
+        let problem = QuestionProblem::UnknownPosition(pos.clone());
+        if !players.position_config.contains(pos) && !problems.contains(&problem) {
+            problems.push(problem);
+        }
+    }
+    if question.player1 == question.player2 && question.pos1 == question.pos2 {
+        problems.push(QuestionProblem::SelfComparison);
+    } else if Stage::of_question(question).is_none() {
+        problems.push(QuestionProblem::MixedPositions);
+    }
+
+    let mut references = vec![&question.player1];
+    if question.player2 != question.player1 {
+        references.push(&question.player2);
+    }
+    for reference in references {
+        if players.find(reference).is_none() {
+            problems.push(QuestionProblem::UnknownPlayer(reference.clone()));
+        }
+    }
+    for (reference, pos) in [
+        (&question.player1, &question.pos1),
+        (&question.player2, &question.pos2),
+    ] {
+        let player = match players.find(reference) {
+            Some(player) => player,
+            None => continue,
+        };
+        let problem = QuestionProblem::CantPlay(player.name.clone(), pos.clone());
+        if players.position_config.contains(pos)
+            && !player.can_play(pos)
+            && !problems.contains(&problem)
+        {
+            problems.push(problem);
+        }
+    }
+    problems
+}
+
+/// Look for mistakes in the questions, like the ones hand edited question files pick up. Issues
+/// come in the order of the questions they're about, answered questions first
+pub fn validate_questions(players: &Players, questions: &Questions) -> Vec<QuestionIssue> {
+    let mut issues = Vec::new();
+
+    let mut answered_before: HashMap<(QuestionKey, Option<&str>), usize> = HashMap::new();
+    for (i, answered) in questions.questions.iter().enumerate() {
+        let question = QuestionRef::Answered(i);
+        let mut problems = check_question(players, &answered.question);
+        if !answered.response.is_finite() || answered.response <= 0.0 {
+            problems.push(QuestionProblem::InvalidResponse(answered.response));
+        }
+        if !answered.weight.is_finite() || answered.weight < 0.0 {
+            problems.push(QuestionProblem::InvalidWeight(answered.weight));
+        }
+        // Several raters answering the same question is fine, one rater answering it twice
+        // probably isn't
+        let key = (question_key(&answered.question), answered.rater.as_deref());
+        if let Some(&first) = answered_before.get(&key) {
+            problems.push(QuestionProblem::Duplicate(QuestionRef::Answered(first)));
+        } else {
+            answered_before.insert(key, i);
+        }
+        issues.extend(
+            problems
+                .into_iter()
+                .map(|problem| QuestionIssue { question, problem }),
+        );
+    }
+
+    let mut skipped_before: HashMap<QuestionKey, usize> = HashMap::new();
+    for (i, skipped) in questions.skipped.iter().enumerate() {
+        let question = QuestionRef::Skipped(i);
+        let mut problems = check_question(players, skipped);
+        if let Some(&first) = skipped_before.get(&question_key(skipped)) {
+            problems.push(QuestionProblem::Duplicate(QuestionRef::Skipped(first)));
+        } else {
+            skipped_before.insert(question_key(skipped), i);
+        }
+        issues.extend(
+            problems
+                .into_iter()
+                .map(|problem| QuestionIssue { question, problem }),
+        );
+    }
+    issues
+}
@@ -1,4 +1,17 @@
-use player_rank_cli::player_rank_lib::{Player, Players};
+use player_rank_cli::player_rank_lib::{AnsweredQuestion, Player, Players, Position, Question};
+
+// The positions in the default position config
+pub fn atk() -> Position {
+    Position::new("Atk")
+}
+
+pub fn def() -> Position {
+    Position::new("Def")
+}
+
+pub fn goalie() -> Position {
+    Position::new("Goalie")
+}
 
 pub fn test_players(num_players: usize) -> Players {
     let names = vec![
@@ -33,14 +46,21 @@ pub fn test_players(num_players: usize) -> Players {
     assert!(num_players <= names.len());
 
     let mut players = Players::new();
+    players.weeks = vec![String::from("week1"), String::from("week2")];
 
     // Define a closure for adding players
     let mut add_player = |name: String, goalie: bool| {
+        let mut positions = vec![atk(), def()];
+        if goalie {
+            positions.push(self::goalie());
+        }
         players.players.push(Player {
+            id: name.clone(),
             name,
-            goalie,
-            avail1: false,
-            avail2: false,
+            aliases: Vec::new(),
+            positions,
+            availability: vec![false, false],
+            metadata: Default::default(),
         });
     };
 
@@ -50,3 +70,30 @@ pub fn test_players(num_players: usize) -> Players {
 
     players
 }
+
+pub fn answer(
+    player1: &str,
+    pos1: Position,
+    player2: &str,
+    pos2: Position,
+    response: f64,
+) -> AnsweredQuestion {
+    AnsweredQuestion::new(
+        Question {
+            player1: String::from(player1),
+            pos1,
+            player2: String::from(player2),
+            pos2,
+        },
+        response,
+    )
+}
+
+// A fresh, empty directory for a test to write files in
+pub fn temp_dir(test_name: &str) -> std::path::PathBuf {
+    let dir =
+        std::env::temp_dir().join(format!("player_rank_{}_{}", test_name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
@@ -1,14 +1,15 @@
 // Import common module
 mod common;
 
+use clap::Parser;
 use player_rank_cli::player_rank_lib::*;
 
 #[test]
 fn get_first_question() {
     // Create a player rank
     let players = common::test_players(26);
-    let mut questions = Questions::new();
-    let mut player_rank = PlayerRank::new(&players, &mut questions, Some(0));
+    let questions = Questions::new();
+    let mut player_rank = PlayerRank::new(players, questions, Some(0)).unwrap();
 
     // Get first question
     let (question, status) = player_rank.get_next_question();
@@ -18,14 +19,19 @@ fn get_first_question() {
         question,
         Some(Question {
             player1: String::from("Samuel"),
-            pos1: Position::Atk,
+            pos1: common::atk(),
             player2: String::from("William"),
-            pos2: Position::Atk
+            pos2: common::atk()
         })
     );
 
     // Should start in the attack stage
-    assert_eq!(status, Some(QuestionStatus::StartingStage(Stage::first())));
+    assert_eq!(
+        status,
+        Some(QuestionStatus::StartingStage(Stage::first(
+            &PositionConfig::default()
+        )))
+    );
 }
 
 #[test]
@@ -33,13 +39,1528 @@ fn get_first_question() {
 fn repeatedly_skip() {
     // Create a player rank
     let players = common::test_players(4);
-    let mut questions = Questions::new();
-    let mut player_rank = PlayerRank::new(&players, &mut questions, Some(0));
+    let questions = Questions::new();
+    let mut player_rank = PlayerRank::new(players, questions, Some(0)).unwrap();
 
     // Get the first 20 questions
     for _ in 0..20 {
         let (question, status) = player_rank.get_next_question();
-        player_rank.give_response(1.0);
+        let _ = player_rank.give_response(1.0);
         println!("q: '{:?}', s: '{:?}'", question, status);
     }
 }
+
+#[test]
+fn rank_from_consistent_answers() {
+    let players = common::test_players(4);
+    let mut questions = Questions::new();
+
+    // Alice is twice as good as Bob, who is twice as good as Charlotte, who is as good as David
+    questions.questions.extend([
+        common::answer("Alice", common::atk(), "Bob", common::atk(), 2.0),
+        common::answer("Bob", common::atk(), "Charlotte", common::atk(), 2.0),
+        common::answer("Charlotte", common::atk(), "David", common::atk(), 1.0),
+        common::answer("Alice", common::atk(), "David", common::atk(), 4.0),
+    ]);
+
+    let ranks = LogLeastSquares.rank(&players, &questions).unwrap();
+    let atk: Vec<f64> = ranks
+        .ranks
+        .iter()
+        .map(|rank| rank.skill(&common::atk()).unwrap())
+        .collect();
+
+    assert!((atk[0] / atk[1] - 2.0).abs() < 1e-9);
+    assert!((atk[1] / atk[2] - 2.0).abs() < 1e-9);
+    assert!((atk[2] / atk[3] - 1.0).abs() < 1e-9);
+
+    // Skills are scaled to a geometric mean of 1
+    let log_mean: f64 = atk.iter().map(|a| a.ln()).sum::<f64>() / atk.len() as f64;
+    assert!(log_mean.abs() < 1e-9);
+
+    // Nobody has been compared in any other position
+    assert!(ranks
+        .ranks
+        .iter()
+        .all(|rank| rank.skill(&common::def()) == Some(1.0)
+            && rank.skill(&common::goalie()).is_none()));
+}
+
+#[test]
+fn resume_from_answered_questions() {
+    let players = common::test_players(4);
+    let mut questions = Questions::new();
+
+    // A previous session finished the attack stage and linked two players on defense
+    questions.questions.extend([
+        common::answer("Alice", common::atk(), "Bob", common::atk(), 1.5),
+        common::answer("Bob", common::atk(), "Charlotte", common::atk(), 0.8),
+        common::answer("David", common::atk(), "Charlotte", common::atk(), 1.1),
+        common::answer("Alice", common::def(), "Bob", common::def(), 1.2),
+    ]);
+    let mut player_rank = PlayerRank::new(players, questions, Some(0)).unwrap();
+
+    // Questioning picks up in the defense stage
+    let (question, status) = player_rank.get_next_question();
+    assert_eq!(
+        status,
+        Some(QuestionStatus::StartingStage(
+            Stage::Position(common::def())
+        ))
+    );
+
+    // Only two more links are needed to connect everyone on defense
+    let mut asked = Vec::new();
+    let mut question = question;
+    while let Some(q) = question {
+        if q.pos1 != common::def() {
+            break;
+        }
+        assert_ne!((q.player1.as_str(), q.player2.as_str()), ("Alice", "Bob"));
+        asked.push(q);
+        assert!(player_rank.give_response(1.0).is_ok());
+        question = player_rank.get_next_question().0;
+    }
+    assert_eq!(asked.len(), 2);
+}
+
+#[test]
+fn resume_past_finished_stage() {
+    let players = common::test_players(4);
+    let names = ["Alice", "Bob", "Charlotte", "David"];
+    let mut questions = Questions::new();
+
+    // A previous session asked every attack question, linked everyone on defense and linked
+    // attack to defense
+    for (i, player1) in names.iter().enumerate() {
+        for player2 in &names[i + 1..] {
+            questions.questions.push(common::answer(
+                player1,
+                common::atk(),
+                player2,
+                common::atk(),
+                1.1,
+            ));
+        }
+    }
+    for pair in names.windows(2) {
+        questions.questions.push(common::answer(
+            pair[0],
+            common::def(),
+            pair[1],
+            common::def(),
+            0.9,
+        ));
+    }
+    questions.questions.push(common::answer(
+        "Alice",
+        common::atk(),
+        "Alice",
+        common::def(),
+        1.0,
+    ));
+
+    // Attack has nothing left to ask, so the extra questions start on defense
+    for seed in 0..5 {
+        let mut player_rank =
+            PlayerRank::new(players.clone(), questions.clone(), Some(seed)).unwrap();
+        let (question, status) = player_rank.get_next_question();
+        assert_eq!(
+            status,
+            Some(QuestionStatus::AllMandatoryQuestionsAnswered(
+                Stage::Position(common::def())
+            ))
+        );
+        assert_eq!(question.unwrap().pos1, common::def());
+    }
+}
+
+#[test]
+fn skip_after_resuming() {
+    let mut players = common::test_players(5);
+    for player in &mut players.players[..3] {
+        player.positions.push(common::goalie());
+    }
+
+    // An old answer compares two players in goal who aren't goalies anymore
+    let mut questions = Questions::new();
+    questions.questions.push(common::answer(
+        "David",
+        common::goalie(),
+        "Emily",
+        common::goalie(),
+        1.3,
+    ));
+
+    for seed in 0..10 {
+        let mut player_rank =
+            PlayerRank::new(players.clone(), questions.clone(), Some(seed)).unwrap();
+        let mut question = player_rank.get_next_question().0;
+        while let Some(q) = &question {
+            if q.pos1 == common::goalie() {
+                break;
+            }
+            assert!(player_rank.give_response(1.0).is_ok());
+            question = player_rank.get_next_question().0;
+        }
+
+        // Skipping the first goalie question gets a replacement between goalies
+        let skipped = question.unwrap();
+        let (replacement, _) = player_rank.get_next_question();
+        let replacement = replacement.unwrap();
+        assert_eq!(replacement.pos1, common::goalie());
+        assert_ne!(replacement, skipped);
+        for player in [&replacement.player1, &replacement.player2] {
+            assert!(players.find(player).unwrap().can_play(&common::goalie()));
+        }
+    }
+}
+
+#[test]
+fn scripted_ranking_session() {
+    let players = common::test_players(4);
+    let questions = Questions::new();
+    let mut player_rank = PlayerRank::new(players, questions, Some(0)).unwrap();
+
+    // Answer, skip, give an unparseable response, give an invalid ratio, answer, then quit
+    let mut input = std::io::Cursor::new("1.5\ns\nabc\n-2\n0.5\nq\n");
+    let ranks =
+        player_rank_cli::run_ranking(&mut player_rank, &mut input, &LogLeastSquares).unwrap();
+    assert_eq!(ranks.ranks.len(), 4);
+
+    let responses: Vec<f64> = player_rank
+        .questions()
+        .questions
+        .iter()
+        .map(|q| q.response)
+        .collect();
+    assert_eq!(responses, vec![1.5, 0.5]);
+}
+
+#[test]
+fn rank_uncertainty() {
+    let players = common::test_players(4);
+    let chain = [
+        common::answer("Alice", common::atk(), "Bob", common::atk(), 2.0),
+        common::answer("Bob", common::atk(), "Charlotte", common::atk(), 2.0),
+        common::answer("Charlotte", common::atk(), "David", common::atk(), 2.0),
+    ];
+
+    // A redundant answer that agrees with the chain
+    let mut consistent = Questions::new();
+    consistent.questions.extend(chain.clone());
+    consistent.questions.push(common::answer(
+        "Alice",
+        common::atk(),
+        "Charlotte",
+        common::atk(),
+        4.0,
+    ));
+    let consistent = LogLeastSquares.rank(&players, &consistent).unwrap();
+
+    // A redundant answer that contradicts the chain
+    let mut contradictory = Questions::new();
+    contradictory.questions.extend(chain);
+    contradictory.questions.push(common::answer(
+        "Alice",
+        common::atk(),
+        "Charlotte",
+        common::atk(),
+        0.5,
+    ));
+    let contradictory = LogLeastSquares.rank(&players, &contradictory).unwrap();
+
+    let consistent_err = consistent.ranks[0].std_err(&common::atk()).unwrap();
+    let contradictory_err = contradictory.ranks[0].std_err(&common::atk()).unwrap();
+    assert!(consistent_err < contradictory_err);
+
+    // David only has a single comparison, so he's less certain than Bob
+    assert!(
+        consistent.ranks[3].std_err(&common::atk()).unwrap()
+            > consistent.ranks[1].std_err(&common::atk()).unwrap()
+    );
+
+    // Nobody has been compared on defense
+    assert!(consistent
+        .ranks
+        .iter()
+        .all(|rank| rank.std_err(&common::def()).is_none()));
+}
+
+#[test]
+fn find_contradicting_answer() {
+    let players = common::test_players(4);
+    let mut questions = Questions::new();
+
+    // Every player is 1.2 times better than the next, but someone typed 12 instead of 1.728
+    questions.questions.extend([
+        common::answer("Alice", common::atk(), "Bob", common::atk(), 1.2),
+        common::answer("Bob", common::atk(), "Charlotte", common::atk(), 1.2),
+        common::answer("Charlotte", common::atk(), "David", common::atk(), 1.2),
+        common::answer("Alice", common::atk(), "Charlotte", common::atk(), 1.44),
+        common::answer("Bob", common::atk(), "David", common::atk(), 1.44),
+        common::answer("Alice", common::atk(), "David", common::atk(), 12.0),
+        common::answer("Alice", common::def(), "Bob", common::def(), 1.0),
+    ]);
+
+    let report = find_inconsistencies(&players, &questions).unwrap();
+    assert_eq!(report.suspects.len(), 1);
+    assert_eq!(report.suspects[0].index, 5);
+
+    // The worst loop goes through the bad answer, and is off by a factor of 12 / 1.728
+    let worst = &report.cycles[0];
+    assert_eq!(worst.stage, Stage::Position(common::atk()));
+    assert!(worst.answers.contains(&5));
+    assert!((worst.log_error.abs() - (12.0f64 / 1.728).ln()).abs() < 1e-9);
+}
+
+#[test]
+fn solvers_agree_on_consistent_answers() {
+    let players = common::test_players(4);
+    let mut questions = Questions::new();
+    questions.questions.extend([
+        common::answer("Alice", common::def(), "Bob", common::def(), 3.0),
+        common::answer("Bob", common::def(), "Charlotte", common::def(), 0.5),
+        common::answer("Alice", common::def(), "Charlotte", common::def(), 1.5),
+        common::answer("David", common::def(), "Charlotte", common::def(), 2.0),
+    ]);
+
+    let solvers: Vec<Box<dyn RankingSolver>> = vec![
+        Box::new(LogLeastSquares),
+        Box::new(Eigenvector),
+        Box::new(BradleyTerry),
+    ];
+    for solver in solvers {
+        let ranks = solver.rank(&players, &questions).unwrap();
+        let def: Vec<f64> = ranks
+            .ranks
+            .iter()
+            .map(|rank| rank.skill(&common::def()).unwrap())
+            .collect();
+        let expected = [3.0, 1.0, 2.0, 4.0];
+        for (skill, expected) in def.iter().zip(expected) {
+            assert!((skill / def[1] - expected).abs() < 1e-6);
+        }
+    }
+}
+
+#[test]
+fn self_ratings_calibrate_positions() {
+    let players = common::test_players(2);
+    let mut questions = Questions::new();
+
+    // Alice is the better attacker, Bob the better defender, and Alice attacks 3 times better than
+    // she defends
+    questions.questions.extend([
+        common::answer("Alice", common::atk(), "Bob", common::atk(), 2.0),
+        common::answer("Alice", common::def(), "Bob", common::def(), 0.5),
+        common::answer("Alice", common::atk(), "Alice", common::def(), 3.0),
+    ]);
+
+    let ranks = LogLeastSquares.rank(&players, &questions).unwrap();
+    let (alice, bob) = (&ranks.ranks[0], &ranks.ranks[1]);
+    assert!(
+        (alice.skill(&common::atk()).unwrap() / alice.skill(&common::def()).unwrap() - 3.0).abs()
+            < 1e-9
+    );
+    assert!(
+        (bob.skill(&common::atk()).unwrap() / bob.skill(&common::def()).unwrap() - 0.75).abs()
+            < 1e-9
+    );
+}
+
+#[test]
+fn build_balanced_teams() {
+    let mut players = common::test_players(11);
+    let mut ranks = Ranks::new();
+    for (i, player) in players.players.iter_mut().enumerate() {
+        // Everyone but Kate can make it in week 1, and Alice and Bob can play in goal
+        player.availability[0] = player.name != "Kate";
+        let mut rank = Rank::new(player);
+        rank.skills.insert(common::atk(), 1.0 + i as f64 * 0.1);
+        rank.skills.insert(common::def(), 2.0 - i as f64 * 0.1);
+        if i < 2 {
+            player.positions.push(common::goalie());
+            rank.skills.insert(common::goalie(), 1.0 + i as f64);
+        }
+        ranks.ranks.push(rank);
+    }
+
+    let teams = build_teams(&players, &ranks, 1, 2).unwrap();
+    assert_eq!(teams.teams.len(), 2);
+
+    // Each team gets one of the goalies and half of the others
+    let mut goalies: Vec<String> = teams
+        .teams
+        .iter()
+        .flat_map(|t| t.specialists.get(&common::goalie()).cloned())
+        .collect();
+    goalies.sort();
+    assert_eq!(goalies, vec!["Alice", "Bob"]);
+    let mut sizes: Vec<usize> = teams.teams.iter().map(|t| t.players.len()).collect();
+    sizes.sort();
+    assert_eq!(sizes, vec![4, 4]);
+    assert!(teams
+        .teams
+        .iter()
+        .all(|t| !t.players.contains(&String::from("Kate"))));
+
+    assert!(teams.spreads[&common::atk()] < 0.2);
+    assert!(teams.spreads[&common::def()] < 0.2);
+
+    // Only the goalies can make it in week 2, so nobody plays on the field
+    for player in players.players.iter_mut().take(2) {
+        player.availability[1] = true;
+    }
+    let teams = build_teams(&players, &ranks, 2, 2).unwrap();
+    for team in &teams.teams {
+        assert!(team.players.is_empty());
+        for pos in [common::atk(), common::def()] {
+            assert_eq!(team.skills[&pos], 0.0);
+            assert!(team.skills[&pos].is_sign_positive());
+        }
+    }
+}
+
+#[test]
+fn only_goalies_asked_about_goal() {
+    let mut players = common::test_players(6);
+    for player in players.players.iter_mut().take(3) {
+        player.positions.push(common::goalie());
+    }
+    let goalies = ["Alice", "Bob", "Charlotte"];
+
+    let questions = Questions::new();
+    let mut player_rank = PlayerRank::new(players, questions, Some(0)).unwrap();
+
+    // Answer every question
+    while let (Some(question), _) = player_rank.get_next_question() {
+        for (player, pos) in [
+            (&question.player1, question.pos1),
+            (&question.player2, question.pos2),
+        ] {
+            if pos == common::goalie() {
+                assert!(goalies.contains(&player.as_str()));
+            }
+        }
+        assert!(player_rank.give_response(1.5).is_ok());
+    }
+    let ranks = player_rank.get_ranking().unwrap();
+
+    for rank in &ranks.ranks {
+        let is_goalie = goalies.contains(&rank.name.as_str());
+        assert_eq!(rank.skill(&common::goalie()).is_some(), is_goalie);
+    }
+}
+
+#[test]
+fn count_stage_coverage() {
+    let mut players = common::test_players(4);
+    players.players[0].positions.push(common::goalie());
+    players.players[1].positions.push(common::goalie());
+
+    let mut questions = Questions::new();
+    questions.questions.extend([
+        common::answer("Alice", common::atk(), "Bob", common::atk(), 2.0),
+        // Asked the other way round, so it's the same question
+        common::answer("Bob", common::atk(), "Alice", common::atk(), 0.5),
+        common::answer("Bob", common::atk(), "Charlotte", common::atk(), 1.0),
+        common::answer("Alice", common::atk(), "Alice", common::goalie(), 1.0),
+    ]);
+
+    let coverage = stage_coverage(&players, &questions);
+    let stages: Vec<Stage> = coverage.iter().map(|c| c.stage.clone()).collect();
+    assert_eq!(
+        stages,
+        vec![
+            Stage::Position(common::atk()),
+            Stage::Position(common::def()),
+            Stage::Position(common::goalie()),
+            Stage::SelfRating
+        ]
+    );
+
+    let atk = &coverage[0];
+    assert_eq!((atk.answered, atk.possible), (2, 6));
+    assert_eq!(atk.least_compared, vec!["David"]);
+    assert_eq!(atk.least_answers, 0);
+
+    // Only the two goalies can be compared in goal
+    assert_eq!(coverage[2].possible, 1);
+
+    // Goalies can compare three positions, everyone else two
+    let self_rating = &coverage[3];
+    assert_eq!((self_rating.answered, self_rating.possible), (1, 8));
+}
+
+#[test]
+fn rank_file_is_created_and_replaced() {
+    let dir = common::temp_dir("rank_file_is_created_and_replaced");
+    let player_file = dir.join("players.csv");
+    let question_file = dir.join("questions.csv");
+    let rank_file = dir.join("ranks.csv");
+
+    let rank = |players: &str| {
+        std::fs::write(&player_file, players).unwrap();
+        let args = player_rank_cli::Cli::try_parse_from([
+            "player_rank_cli",
+            "rank",
+            player_file.to_str().unwrap(),
+            question_file.to_str().unwrap(),
+            rank_file.to_str().unwrap(),
+            "--allow-disconnected",
+        ])
+        .unwrap();
+        player_rank_cli::run(args).unwrap();
+        std::fs::read_to_string(&rank_file).unwrap().lines().count()
+    };
+
+    // Neither the question file nor the rank file exist yet
+    let header = "name,goalie,week1,week2\n";
+    assert_eq!(
+        rank(&format!(
+            "{header}A,true,true,true\nB,false,true,true\nC,false,true,true\n"
+        )),
+        4
+    );
+
+    // Writing fewer ranks doesn't leave the old ones behind
+    assert_eq!(rank(&format!("{header}A,true,true,true\n")), 2);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn question_file_backups_rotate() {
+    let dir = common::temp_dir("question_file_backups_rotate");
+    let input = dir.join("in.csv");
+    let output = dir.join("questions.csv");
+    let backup = |n: usize| dir.join(format!("questions.csv.bak.{}", n));
+
+    // Each write backs up the version before it, and only the last 3 backups are kept
+    let mut versions = Vec::new();
+    for response in 1..=5 {
+        std::fs::write(
+            &input,
+            format!(
+                "player1,player1_pos,player2,player2_pos,skill_factor\nA,Atk,B,Atk,{response}\n"
+            ),
+        )
+        .unwrap();
+        let args = player_rank_cli::Cli::try_parse_from([
+            "player_rank_cli",
+            "merge",
+            output.to_str().unwrap(),
+            input.to_str().unwrap(),
+        ])
+        .unwrap();
+        player_rank_cli::run(args).unwrap();
+        versions.push(std::fs::read_to_string(&output).unwrap());
+
+        for n in 1..=3 {
+            match versions.len().checked_sub(n + 1) {
+                Some(older) => {
+                    assert_eq!(std::fs::read_to_string(backup(n)).unwrap(), versions[older])
+                }
+                None => assert!(!backup(n).exists()),
+            }
+        }
+        assert!(!backup(4).exists());
+    }
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn saved_session_needs_unchanged_files() {
+    let dir = common::temp_dir("saved_session_needs_unchanged_files");
+    let player_file = dir.join("players.csv");
+    let question_file = dir.join("questions.csv");
+    let state_file = dir.join("state.json");
+
+    // A session saved part way through
+    let mut questions = Questions::new();
+    questions.questions.push(common::answer(
+        "Alice",
+        common::atk(),
+        "Bob",
+        common::atk(),
+        1.5,
+    ));
+    let mut player_rank = PlayerRank::new(common::test_players(4), questions, Some(0)).unwrap();
+    player_rank.get_next_question();
+    let state = serde_json::to_string(&player_rank.save_state()).unwrap();
+    std::fs::write(&state_file, state).unwrap();
+    std::fs::write(
+        &player_file,
+        "name,goalie,week1,week2\n\
+         Alice,false,false,false\n\
+         Bob,false,false,false\n\
+         Charlotte,false,false,false\n\
+         David,false,false,false\n",
+    )
+    .unwrap();
+
+    // An answer added by hand since then isn't in the session, so it isn't picked up and the
+    // question file is left alone
+    let edited = "player1,player1_pos,player2,player2_pos,skill_factor,rater,weight\n\
+                  Alice,Atk,Bob,Atk,1.5,,1.0\n\
+                  Alice,Def,David,Def,2.0,bob,1.0\n";
+    std::fs::write(&question_file, edited).unwrap();
+    let args = player_rank_cli::Cli::try_parse_from([
+        "player_rank_cli",
+        "ask",
+        player_file.to_str().unwrap(),
+        question_file.to_str().unwrap(),
+        dir.join("ranks.csv").to_str().unwrap(),
+        "--state",
+        state_file.to_str().unwrap(),
+    ])
+    .unwrap();
+    let err = player_rank_cli::run(args).unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("has changed since the session was saved"));
+    assert_eq!(std::fs::read_to_string(&question_file).unwrap(), edited);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn engine_outlives_its_inputs() {
+    // The engine owns everything it needs, so it can be stored and moved to another thread
+    let mut player_rank = {
+        let players = common::test_players(4);
+        PlayerRank::new(players, Questions::new(), Some(0)).unwrap()
+    };
+    let (question, _) = player_rank.get_next_question();
+    assert_eq!(player_rank.current_question(), question);
+
+    let handle = std::thread::spawn(move || {
+        assert!(player_rank.give_response(2.0).is_ok());
+        player_rank
+    });
+    let player_rank = handle.join().unwrap();
+
+    assert_eq!(player_rank.current_question(), None);
+    assert_eq!(player_rank.players().players.len(), 4);
+    let (_, questions) = player_rank.into_parts();
+    assert_eq!(questions.questions.len(), 1);
+    assert_eq!(Some(questions.questions[0].question.clone()), question);
+}
+
+#[test]
+fn resume_from_saved_state() {
+    let players = common::test_players(5);
+    let mut player_rank = PlayerRank::new(players, Questions::new(), Some(0)).unwrap();
+
+    // Answer a question, skip one, and leave the next waiting for a response
+    player_rank.get_next_question();
+    assert!(player_rank.give_response(1.5).is_ok());
+    let (skipped, _) = player_rank.get_next_question();
+    let (pending, _) = player_rank.get_next_question();
+
+    let json = serde_json::to_string(&player_rank.save_state()).unwrap();
+    let mut restored = PlayerRank::from_state(serde_json::from_str(&json).unwrap()).unwrap();
+    assert_eq!(restored.current_question(), pending);
+    assert_eq!(restored.questions().questions.len(), 1);
+
+    // Both carry on asking exactly the same questions, and never ask the skipped one again
+    let skipped = skipped.unwrap();
+    loop {
+        assert!(player_rank.give_response(1.2).is_ok());
+        assert!(restored.give_response(1.2).is_ok());
+        let (question, status) = player_rank.get_next_question();
+        assert_eq!(restored.get_next_question(), (question.clone(), status));
+        match question {
+            Some(question) => assert_ne!(question, skipped),
+            None => break,
+        }
+    }
+
+    // A state with questions about players it doesn't have can't be picked up
+    let mut state: serde_json::Value = serde_json::from_str(&json).unwrap();
+    state["questions"]["skipped"][0]["player1"] = serde_json::json!("Zed");
+    let state = serde_json::from_value(state).unwrap();
+    assert_eq!(
+        PlayerRank::from_state(state).err(),
+        Some(Error::UnknownPlayer(String::from("Zed")))
+    );
+
+    // Neither can one whose questions waiting to be asked are about players it doesn't have
+    let mut state: serde_json::Value = serde_json::from_str(&json).unwrap();
+    state["questions"] = serde_json::json!({ "questions": [], "skipped": [] });
+    state["players"]["players"]
+        .as_array_mut()
+        .unwrap()
+        .truncate(2);
+    let state = serde_json::from_value(state).unwrap();
+    assert!(matches!(
+        PlayerRank::from_state(state),
+        Err(Error::InvalidPlayerIndex { players: 2, .. })
+    ));
+}
+
+#[test]
+fn saved_session_keeps_strategy() {
+    let strategies: Vec<Box<dyn QuestionStrategy>> = vec![
+        Box::new(MostInformative),
+        Box::new(RandomOrder),
+        Box::new(RoundRobin::new()),
+    ];
+    for strategy in strategies {
+        let mut player_rank =
+            PlayerRank::new(common::test_players(5), Questions::new(), Some(0)).unwrap();
+        player_rank.set_strategy(strategy);
+        for _ in 0..12 {
+            player_rank.get_next_question();
+            assert!(player_rank.give_response(1.5).is_ok());
+        }
+
+        // The restored session picks questions the same way, part way through a round robin too
+        let json = serde_json::to_string(&player_rank.save_state()).unwrap();
+        let mut restored = PlayerRank::from_state(serde_json::from_str(&json).unwrap()).unwrap();
+        loop {
+            let (question, status) = player_rank.get_next_question();
+            assert_eq!(restored.get_next_question(), (question.clone(), status));
+            if question.is_none() {
+                break;
+            }
+            assert!(player_rank.give_response(1.2).is_ok());
+            assert!(restored.give_response(1.2).is_ok());
+        }
+    }
+}
+
+#[test]
+fn skipped_questions_not_asked_again() {
+    let players = common::test_players(4);
+    let mut questions = Questions::new();
+
+    // A previous session skipped comparing Alice and Bob on attack
+    let skipped = Question {
+        player1: String::from("Alice"),
+        pos1: common::atk(),
+        player2: String::from("Bob"),
+        pos2: common::atk(),
+    };
+    let reversed = Question {
+        player1: String::from("Bob"),
+        pos1: common::atk(),
+        player2: String::from("Alice"),
+        pos2: common::atk(),
+    };
+    questions.skipped.push(skipped.clone());
+
+    for seed in 0..10 {
+        let mut player_rank =
+            PlayerRank::new(players.clone(), questions.clone(), Some(seed)).unwrap();
+        while let (Some(question), _) = player_rank.get_next_question() {
+            assert_ne!(question, skipped);
+            assert_ne!(question, reversed);
+            assert!(player_rank.give_response(1.5).is_ok());
+        }
+
+        // Everyone still got linked up on attack
+        let ranks = player_rank.get_ranking().unwrap();
+        assert!(ranks
+            .ranks
+            .iter()
+            .all(|rank| rank.std_err(&common::atk()).is_some()));
+    }
+}
+
+#[test]
+fn skipped_partners_still_linked() {
+    let players = common::test_players(5);
+    let skip = |player1: &str, player2: &str| Question {
+        player1: String::from(player1),
+        pos1: common::atk(),
+        player2: String::from(player2),
+        pos2: common::atk(),
+    };
+
+    // David and Emily are linked, but every question between David and anyone else was skipped
+    let mut questions = Questions::new();
+    questions.questions.push(common::answer(
+        "David",
+        common::atk(),
+        "Emily",
+        common::atk(),
+        1.2,
+    ));
+    for other in ["Alice", "Bob", "Charlotte"] {
+        questions.skipped.push(skip("David", other));
+    }
+
+    // Answer every attack question, and return who ended up linked to who on attack
+    let atk_components = |questions: &Questions, seed: u64, all_linked: bool| {
+        let mut player_rank =
+            PlayerRank::new(players.clone(), questions.clone(), Some(seed)).unwrap();
+        let mut question = player_rank.get_next_question();
+        assert_eq!(question.0.as_ref().unwrap().pos1, common::atk());
+        assert_eq!(
+            question.1 == Some(QuestionStatus::AllQuestionsSkipped),
+            !all_linked
+        );
+        while let (Some(q), _) = &question {
+            if q.pos1 != common::atk() {
+                break;
+            }
+            assert!(player_rank.give_response(1.5).is_ok());
+            question = player_rank.get_next_question();
+        }
+        let connectivity = stage_connectivity(player_rank.players(), player_rank.questions());
+        connectivity
+            .into_iter()
+            .find(|stage| stage.stage == Stage::Position(common::atk()))
+            .unwrap()
+            .components
+    };
+
+    // Emily links them to everyone else instead
+    for seed in 0..10 {
+        assert_eq!(atk_components(&questions, seed, true).len(), 1);
+    }
+
+    // With Emily's questions skipped too, there's no way to link them, and the user gets told.
+    // Everyone else still gets linked, whoever comes first
+    let mut all_skipped = questions.clone();
+    for other in ["Alice", "Bob", "Charlotte"] {
+        all_skipped.skipped.push(skip("Emily", other));
+    }
+    for seed in 0..10 {
+        let components = atk_components(&all_skipped, seed, false);
+        assert_eq!(components.len(), 2);
+        assert_eq!(components[0].len(), 3);
+    }
+
+    // The same goes for a single player with every question skipped and nothing answered
+    let mut questions = Questions::new();
+    for other in ["Alice", "Bob", "Charlotte", "David"] {
+        questions.skipped.push(skip("Emily", other));
+    }
+    for seed in 0..10 {
+        let components = atk_components(&questions, seed, false);
+        assert_eq!(
+            components,
+            [vec!["Alice", "Bob", "Charlotte", "David"], vec!["Emily"]]
+        );
+    }
+}
+
+#[test]
+fn rater_weights_shift_ranking() {
+    let players = common::test_players(2);
+
+    // Two captains disagree about Alice and Bob
+    let mut questions = Questions::new();
+    for (rater, response) in [("Kim", 2.0), ("Lee", 0.5)] {
+        let mut answered = common::answer("Alice", common::atk(), "Bob", common::atk(), response);
+        answered.rater = Some(String::from(rater));
+        questions.questions.push(answered);
+    }
+
+    let solvers: [&dyn RankingSolver; 3] = [&LogLeastSquares, &Eigenvector, &BradleyTerry];
+    for solver in solvers {
+        // Counted equally, they cancel out
+        let ranks = solver.rank(&players, &questions).unwrap();
+        assert!(
+            (ranks.ranks[0].skill(&common::atk()).unwrap()
+                - ranks.ranks[1].skill(&common::atk()).unwrap())
+            .abs()
+                < 1e-6
+        );
+
+        // Trusting Kim more puts Alice ahead
+        let mut weighted = questions.clone();
+        weighted.set_rater_weight("Kim", 3.0);
+        let ranks = solver.rank(&players, &weighted).unwrap();
+        assert!(
+            ranks.ranks[0].skill(&common::atk()).unwrap()
+                > ranks.ranks[1].skill(&common::atk()).unwrap() * 1.2
+        );
+    }
+}
+
+#[test]
+fn merge_rater_question_files() {
+    let dir = common::temp_dir("merge_rater_question_files");
+    let header = "player1,player1_pos,player2,player2_pos,skill_factor";
+    std::fs::write(
+        dir.join("kim.csv"),
+        format!("{header}\nAlice,Atk,Bob,Atk,2\nBob,Atk,Charlotte,Atk,\n"),
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("lee.csv"),
+        format!("{header},rater,weight\nAlice,Atk,Bob,Atk,0.5,Lee,1\n"),
+    )
+    .unwrap();
+    let merged = dir.join("merged.csv");
+
+    let args = player_rank_cli::Cli::try_parse_from([
+        "player_rank_cli",
+        "merge",
+        merged.to_str().unwrap(),
+        dir.join("kim.csv").to_str().unwrap(),
+        dir.join("lee.csv").to_str().unwrap(),
+        "--weight",
+        "kim=3",
+    ])
+    .unwrap();
+    player_rank_cli::run(args).unwrap();
+
+    let lines: Vec<String> = std::fs::read_to_string(&merged)
+        .unwrap()
+        .lines()
+        .map(String::from)
+        .collect();
+    assert_eq!(
+        lines,
+        [
+            "player1,player1_pos,player2,player2_pos,skill_factor,rater,weight",
+            "Alice,Atk,Bob,Atk,2.0,kim,3.0",
+            "Alice,Atk,Bob,Atk,0.5,Lee,1.0",
+            "Bob,Atk,Charlotte,Atk,,,",
+        ]
+    );
+
+    // Weights have to be numbers
+    assert!(player_rank_cli::Cli::try_parse_from([
+        "player_rank_cli",
+        "merge",
+        "out.csv",
+        "in.csv",
+        "--weight",
+        "kim=lots",
+    ])
+    .is_err());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn rater_agreement_report() {
+    let players = common::test_players(4);
+    let mut questions = Questions::new();
+    let mut rate = |rater: &str, player1: &str, player2: &str, response: f64| {
+        let mut answered = common::answer(player1, common::atk(), player2, common::atk(), response);
+        answered.rater = Some(String::from(rater));
+        questions.questions.push(answered);
+    };
+
+    // Kim and Lee see things the same way, Max doesn't
+    for rater in ["Kim", "Lee"] {
+        rate(rater, "Alice", "Bob", 2.0);
+        rate(rater, "Bob", "Charlotte", 1.5);
+        rate(rater, "Charlotte", "David", 1.2);
+    }
+    rate("Max", "Bob", "Alice", 1.0);
+    rate("Max", "Charlotte", "Bob", 2.0);
+
+    let report = rater_agreement(&players, &questions).unwrap();
+
+    // Max asked the other way round still lines up with the others' questions
+    assert_eq!(report.shared_questions.len(), 3);
+    let worst = &report.shared_questions[0];
+    assert_eq!(
+        (
+            worst.question.player1.as_str(),
+            worst.question.player2.as_str()
+        ),
+        ("Bob", "Charlotte")
+    );
+    assert!((worst.spread - 3.0).abs() < 1e-9);
+    assert!(worst.ratings.contains(&(String::from("Max"), 0.5)));
+
+    let kim_lee = report
+        .rater_pairs
+        .iter()
+        .find(|pair| pair.rater1 == "Kim" && pair.rater2 == "Lee")
+        .unwrap();
+    assert_eq!(kim_lee.shared, 3);
+    assert!((kim_lee.typical_ratio - 1.0).abs() < 1e-9);
+    assert_eq!(report.rater_pairs[0].rater2, "Max");
+
+    // Max is furthest from everyone else
+    assert_eq!(report.raters.len(), 3);
+    assert_eq!(report.raters[0].rater, "Max");
+    assert_eq!(report.raters[0].answers, 2);
+}
+
+#[test]
+fn most_informative_question() {
+    let players = common::test_players(5);
+    let mut questions = Questions::new();
+
+    // Everyone is lined up in a chain on attack and defense, and one player rated themselves
+    let names = ["Alice", "Bob", "Charlotte", "David", "Emily"];
+    for pos in [common::atk(), common::def()] {
+        for pair in names.windows(2) {
+            questions.questions.push(common::answer(
+                pair[0],
+                pos.clone(),
+                pair[1],
+                pos.clone(),
+                1.2,
+            ));
+        }
+    }
+    questions.questions.push(common::answer(
+        "Alice",
+        common::atk(),
+        "Alice",
+        common::def(),
+        1.0,
+    ));
+
+    // Closing the loop between the two ends of the chain tells us the most
+    for seed in 0..5 {
+        let mut player_rank =
+            PlayerRank::new(players.clone(), questions.clone(), Some(seed)).unwrap();
+        player_rank.set_strategy(Box::new(MostInformative));
+        let (question, status) = player_rank.get_next_question();
+        assert_eq!(
+            status,
+            Some(QuestionStatus::AllMandatoryQuestionsAnswered(Stage::first(
+                &PositionConfig::default()
+            )))
+        );
+        let question = question.unwrap();
+        let mut ends = [question.player1, question.player2];
+        ends.sort();
+        assert_eq!(ends, ["Alice", "Emily"]);
+        assert_eq!(question.pos1, common::atk());
+    }
+}
+
+// Always asks the first candidate, and remembers what it picked
+struct FirstCandidate {
+    chosen: std::sync::Arc<std::sync::Mutex<Vec<Question>>>,
+}
+
+impl QuestionStrategy for FirstCandidate {
+    fn choose(&mut self, context: &QuestionContext, _rng: &mut dyn rand::RngCore) -> usize {
+        assert!(!context.candidates.is_empty());
+        self.chosen
+            .lock()
+            .unwrap()
+            .push(context.candidates[0].clone());
+        0
+    }
+}
+
+#[test]
+fn custom_question_strategy() {
+    let chosen = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let mut player_rank =
+        PlayerRank::new(common::test_players(5), Questions::new(), Some(0)).unwrap();
+    player_rank.set_strategy(Box::new(FirstCandidate {
+        chosen: chosen.clone(),
+    }));
+
+    let mut asked = Vec::new();
+    while let (Some(question), _) = player_rank.get_next_question() {
+        asked.push(question);
+        assert!(player_rank.give_response(1.5).is_ok());
+    }
+    assert_eq!(asked, *chosen.lock().unwrap());
+}
+
+#[test]
+fn every_strategy_finishes() {
+    let mut players = common::test_players(6);
+    players.players[0].positions.push(common::goalie());
+    players.players[1].positions.push(common::goalie());
+
+    let strategies: Vec<Box<dyn QuestionStrategy>> = vec![
+        Box::new(FewestConnections),
+        Box::new(MostInformative),
+        Box::new(RandomOrder),
+        Box::new(RoundRobin::new()),
+    ];
+    for strategy in strategies {
+        let mut player_rank = PlayerRank::new(players.clone(), Questions::new(), Some(0)).unwrap();
+        player_rank.set_strategy(strategy);
+        while player_rank.get_next_question().0.is_some() {
+            assert!(player_rank.give_response(1.5).is_ok());
+        }
+
+        // Every possible question got asked once: 15 per field position, 1 in goal, and the self
+        // ratings
+        assert_eq!(
+            player_rank.questions().questions.len(),
+            15 + 15 + 1 + 6 + 2 * 2
+        );
+        let ranks = player_rank.get_ranking().unwrap();
+        assert!(ranks
+            .ranks
+            .iter()
+            .all(|rank| rank.std_err(&common::atk()).is_some()));
+    }
+}
+
+#[test]
+fn stage_connectivity_report() {
+    let players = common::test_players(4);
+    let mut questions = Questions::new();
+
+    // A chain on attack, and a loop on defense
+    let names = ["Alice", "Bob", "Charlotte", "David"];
+    for pair in names.windows(2) {
+        for pos in [common::atk(), common::def()] {
+            questions
+                .questions
+                .push(common::answer(pair[0], pos.clone(), pair[1], pos, 1.5));
+        }
+    }
+    questions.questions.push(common::answer(
+        "David",
+        common::def(),
+        "Alice",
+        common::def(),
+        1.5,
+    ));
+
+    let report = stage_connectivity(&players, &questions);
+    let stages: Vec<Stage> = report.iter().map(|c| c.stage.clone()).collect();
+    assert_eq!(
+        stages,
+        [
+            Stage::Position(common::atk()),
+            Stage::Position(common::def()),
+            Stage::Position(common::goalie()),
+            Stage::SelfRating
+        ]
+    );
+
+    // Cutting any answer splits the chain, and the ends only have one answer each
+    let atk = &report[0];
+    assert_eq!(atk.components.len(), 1);
+    assert_eq!(atk.edge_connectivity, 1);
+    assert_eq!(atk.single_answer, ["Alice", "David"]);
+    assert!((atk.algebraic_connectivity - (2.0 - 2f64.sqrt())).abs() < 1e-9);
+
+    // The loop takes two cuts
+    let def = &report[1];
+    assert_eq!(def.edge_connectivity, 2);
+    assert!(def.single_answer.is_empty());
+    assert!((def.algebraic_connectivity - 2.0).abs() < 1e-9);
+
+    // Nobody can play in goal, and no one has rated themselves
+    assert!(report[2].components.is_empty());
+    assert_eq!(report[3].components, [["Atk"], ["Def"]]);
+    assert_eq!(report[3].edge_connectivity, 0);
+    assert_eq!(report[3].algebraic_connectivity, 0.0);
+}
+
+#[test]
+fn disconnected_ranking() {
+    let dir = common::temp_dir("disconnected_ranking");
+    let player_file = dir.join("players.csv");
+    let question_file = dir.join("questions.csv");
+    let rank_file = dir.join("ranks.csv");
+    std::fs::write(
+        &player_file,
+        "name,goalie,week1,week2\nA,false,true,true\nB,false,true,true\nC,false,true,true\n",
+    )
+    .unwrap();
+    // A and B have been compared in attack, but C hasn't, and nobody has in defense
+    std::fs::write(
+        &question_file,
+        "player1,player1_pos,player2,player2_pos,skill_factor\nA,Atk,B,Atk,2\n",
+    )
+    .unwrap();
+
+    let players = common::test_players(3);
+    let mut questions = Questions::new();
+    questions.questions.push(common::answer(
+        "Alice",
+        common::atk(),
+        "Bob",
+        common::atk(),
+        2.0,
+    ));
+    let disconnected = disconnected_positions(&players, &questions);
+    let positions: Vec<Position> = disconnected.iter().map(|d| d.pos.clone()).collect();
+    assert_eq!(positions, [common::atk(), common::def()]);
+    assert_eq!(
+        disconnected[0].groups,
+        [vec!["Alice", "Bob"], vec!["Charlotte"]]
+    );
+    assert_eq!(disconnected[0].group_of("Charlotte"), Some(2));
+
+    let rank = |extra: &[&str]| {
+        let mut args = vec![
+            "player_rank_cli",
+            "rank",
+            player_file.to_str().unwrap(),
+            question_file.to_str().unwrap(),
+            rank_file.to_str().unwrap(),
+        ];
+        args.extend_from_slice(extra);
+        player_rank_cli::run(player_rank_cli::Cli::try_parse_from(args).unwrap())
+    };
+
+    // Groups that can't be compared aren't ranked unless asked to
+    assert!(rank(&[]).is_err());
+    assert!(!rank_file.exists());
+
+    rank(&["--allow-disconnected"]).unwrap();
+    let ranks = std::fs::read_to_string(&rank_file).unwrap();
+    let lines: Vec<&str> = ranks.lines().collect();
+    assert!(lines[0].ends_with(",warning"));
+    assert!(lines[1].ends_with(",Atk group 1 of 2; Def group 1 of 3"));
+    assert!(lines[3].ends_with(",Atk group 2 of 2; Def group 3 of 3"));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn custom_positions() {
+    let dir = common::temp_dir("custom_positions");
+    let player_file = dir.join("players.csv");
+    let question_file = dir.join("questions.csv");
+    let position_file = dir.join("positions.json");
+    let rank_file = dir.join("ranks.csv");
+    std::fs::write(
+        &position_file,
+        r#"{"positions": [{"position": "Hitter"}, {"position": "Setter", "needs_eligibility": true}]}"#,
+    )
+    .unwrap();
+    // Only A and B can set
+    std::fs::write(
+        &player_file,
+        "name,setter,week1,week2\nA,true,true,true\nB,true,true,true\nC,false,true,true\n",
+    )
+    .unwrap();
+    let header = "player1,player1_pos,player2,player2_pos,skill_factor\n";
+    std::fs::write(
+        &question_file,
+        format!("{header}A,Hitter,B,Hitter,2\nB,Hitter,C,Hitter,2\nA,Setter,B,Setter,0.5\n"),
+    )
+    .unwrap();
+
+    let rank = || {
+        let args = player_rank_cli::Cli::try_parse_from([
+            "player_rank_cli",
+            "rank",
+            player_file.to_str().unwrap(),
+            question_file.to_str().unwrap(),
+            rank_file.to_str().unwrap(),
+            "--positions",
+            position_file.to_str().unwrap(),
+        ])
+        .unwrap();
+        player_rank_cli::run(args)
+    };
+    rank().unwrap();
+
+    let ranks = std::fs::read_to_string(&rank_file).unwrap();
+    let lines: Vec<&str> = ranks.lines().collect();
+    assert_eq!(
+        lines[0],
+        "name,hitter,setter,hitter_low,hitter_high,setter_low,setter_high,warning"
+    );
+    // C can't set, so they don't get a setter rating
+    let c: Vec<&str> = lines[3].split(',').collect();
+    assert_eq!((c[0], c[2]), ("C", ""));
+
+    // The stages follow the config
+    let info = |name, needs_eligibility| PositionInfo {
+        position: Position::new(name),
+        needs_eligibility,
+    };
+    let config = &PositionConfig {
+        positions: vec![info("Hitter", false), info("Setter", true)],
+    };
+    let stage = Stage::first(config);
+    assert_eq!(stage, Stage::Position(Position::new("Hitter")));
+    assert_eq!(stage.next(config), Stage::Position(Position::new("Setter")));
+    assert_eq!(stage.next(config).next(config), Stage::SelfRating);
+
+    // Questions about positions that aren't in the config are an error
+    std::fs::write(&question_file, format!("{header}A,Atk,B,Atk,2\n")).unwrap();
+    assert!(rank().is_err());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn sign_up_sheet_player_file() {
+    let dir = common::temp_dir("sign_up_sheet_player_file");
+    let player_file = dir.join("players.csv");
+    let question_file = dir.join("questions.csv");
+    // Headers straight from the sign-up form, with a phone number and a column for every date
+    let sheet = "Full Name,Phone,Goalie?,2024-06-03,2024-06-10,2024-06-17\n\
+                 Alice,555-0100,yes,y,yes,no\n\
+                 Bob,555-0101,no,x,,yes\n\
+                 Charlotte,555-0102,no,yes,yes,yes\n\
+                 David,,No,yes,yes,no\n";
+    std::fs::write(&player_file, sheet).unwrap();
+
+    let teams = |extra: &[&str]| {
+        let mut args = vec![
+            "player_rank_cli",
+            "teams",
+            player_file.to_str().unwrap(),
+            question_file.to_str().unwrap(),
+            "--allow-disconnected",
+            "--column",
+            "Full Name=name",
+            "--column",
+            "Goalie?=goalie",
+        ];
+        args.extend_from_slice(extra);
+        player_rank_cli::run(player_rank_cli::Cli::try_parse_from(args).unwrap())
+    };
+
+    // Weeks can be picked by number or by name
+    teams(&[]).unwrap();
+    teams(&["--week", "2024-06-10", "--teams", "3"]).unwrap();
+    assert!(teams(&["--week", "2024-07-01"]).is_err());
+    assert!(teams(&["--week", "4"]).is_err());
+
+    // Only two people can make the 17th
+    assert!(teams(&["--week", "3", "--teams", "3"]).is_err());
+
+    // Without the mapping there's no name column
+    let args = player_rank_cli::Cli::try_parse_from([
+        "player_rank_cli",
+        "validate",
+        player_file.to_str().unwrap(),
+        question_file.to_str().unwrap(),
+    ])
+    .unwrap();
+    assert!(player_rank_cli::run(args).is_err());
+
+    // Availability has to be a yes or a no
+    std::fs::write(&player_file, sheet.replace("yes,no\n", "maybe,no\n")).unwrap();
+    assert!(teams(&[]).is_err());
+
+    // Players know which weeks they can make
+    let mut players = common::test_players(1);
+    players.players[0].availability = vec![true, false];
+    assert_eq!(players.week_number("week2"), Some(2));
+    assert_eq!(players.week_number("3"), None);
+    assert_eq!(players.players[0].available(1), Some(true));
+    assert_eq!(players.players[0].available(3), None);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn player_ids_and_renames() {
+    let dir = common::temp_dir("player_ids_and_renames");
+    let player_file = dir.join("players.csv");
+    let question_file = dir.join("questions.csv");
+    std::fs::write(
+        &player_file,
+        "id,name,aliases,goalie,week1,week2\n\
+         p1,John,Jon; Jonny,false,true,true\n\
+         p2,Mary,,false,true,true\n\
+         p3,Sam,,false,true,true\n",
+    )
+    .unwrap();
+    std::fs::write(
+        &question_file,
+        "player1,player1_pos,player2,player2_pos,skill_factor\n\
+         Jon,Atk,Mary,Atk,2.0\n\
+         p2,Atk,Samuel,Atk,1.5\n\
+         Zed,Atk,Jonny,Atk,\n",
+    )
+    .unwrap();
+
+    let args = player_rank_cli::Cli::try_parse_from([
+        "player_rank_cli",
+        "rename",
+        player_file.to_str().unwrap(),
+        question_file.to_str().unwrap(),
+        "--rename",
+        "Samuel=Sam",
+    ])
+    .unwrap();
+    player_rank_cli::run(args).unwrap();
+
+    // Every known player is referred to by id, and unknown players are left alone
+    let lines: Vec<String> = std::fs::read_to_string(&question_file)
+        .unwrap()
+        .lines()
+        .map(String::from)
+        .collect();
+    assert_eq!(
+        lines[1..],
+        [
+            "p1,Atk,p2,Atk,2.0,,1.0",
+            "p2,Atk,p3,Atk,1.5,,1.0",
+            "Zed,Atk,p1,Atk,,,",
+        ]
+    );
+
+    // Renaming to someone who doesn't exist is an error
+    let args = player_rank_cli::Cli::try_parse_from([
+        "player_rank_cli",
+        "rename",
+        player_file.to_str().unwrap(),
+        question_file.to_str().unwrap(),
+        "--rename",
+        "Bob=Robert",
+    ])
+    .unwrap();
+    assert!(player_rank_cli::run(args).is_err());
+
+    // Players can be found by id, name or alias, but an alias can't mean two players
+    let mut players = common::test_players(2);
+    players.players[0].aliases.push(String::from("Al"));
+    assert_eq!(players.find("Al").unwrap().name, "Alice");
+    assert_eq!(players.display_name("Al"), "Alice");
+    assert_eq!(players.display_name("Zed"), "Zed");
+    assert!(players.check_references().is_ok());
+    players.players[1].aliases.push(String::from("Al"));
+    assert!(players.check_references().is_err());
+
+    // Questions using old names count for the right players
+    let mut questions = Questions::new();
+    questions.questions.push(common::answer(
+        "Al",
+        common::atk(),
+        "Zed",
+        common::atk(),
+        2.0,
+    ));
+    let unknown = |reference: &str| Error::UnknownPlayer(String::from(reference));
+    assert_eq!(
+        questions.resolve_players(&common::test_players(1)),
+        [unknown("Al"), unknown("Zed")]
+    );
+    players.players[1].aliases.clear();
+    assert_eq!(questions.resolve_players(&players), [unknown("Zed")]);
+    assert_eq!(questions.questions[0].question.player1, "Alice");
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn question_file_validation() {
+    let players = common::test_players(3);
+    let mut questions = Questions::new();
+    let answers = [
+        ("Alice", common::atk(), "Bob", common::atk(), 2.0),
+        ("Alice", common::atk(), "Alice", common::atk(), 1.0),
+        ("Alice", common::atk(), "Bob", common::def(), 1.5),
+        ("Bob", common::def(), "Charlotte", common::def(), 0.0),
+        ("Zed", common::atk(), "Bob", common::atk(), 1.5),
+        ("Charlotte", common::goalie(), "Bob", common::goalie(), 1.5),
+        // The first question asked the other way round
+        ("Bob", common::atk(), "Alice", common::atk(), 0.5),
+    ];
+    for (player1, pos1, player2, pos2, response) in answers {
+        questions
+            .questions
+            .push(common::answer(player1, pos1, player2, pos2, response));
+    }
+    // A different rater answering again is fine
+    let mut again = common::answer("Alice", common::atk(), "Bob", common::atk(), 2.0);
+    again.rater = Some(String::from("Kim"));
+    questions.questions.push(again);
+
+    let issues = validate_questions(&players, &questions);
+    let found: Vec<(QuestionRef, QuestionProblem)> = issues
+        .into_iter()
+        .map(|issue| (issue.question, issue.problem))
+        .collect();
+    assert_eq!(
+        found,
+        [
+            (QuestionRef::Answered(1), QuestionProblem::SelfComparison),
+            (QuestionRef::Answered(2), QuestionProblem::MixedPositions),
+            (
+                QuestionRef::Answered(3),
+                QuestionProblem::InvalidResponse(0.0)
+            ),
+            (
+                QuestionRef::Answered(4),
+                QuestionProblem::UnknownPlayer(String::from("Zed"))
+            ),
+            (
+                QuestionRef::Answered(5),
+                QuestionProblem::CantPlay(String::from("Charlotte"), common::goalie())
+            ),
+            (
+                QuestionRef::Answered(5),
+                QuestionProblem::CantPlay(String::from("Bob"), common::goalie())
+            ),
+            (
+                QuestionRef::Answered(6),
+                QuestionProblem::Duplicate(QuestionRef::Answered(0))
+            ),
+        ]
+    );
+    assert!(QuestionProblem::SelfComparison.is_error());
+    assert!(!QuestionProblem::Duplicate(QuestionRef::Answered(0)).is_error());
+
+    // Errors stop the command, warnings don't
+    let dir = common::temp_dir("question_file_validation");
+    let player_file = dir.join("players.csv");
+    let question_file = dir.join("questions.csv");
+    std::fs::write(
+        &player_file,
+        "name,goalie,week1,week2\nA,false,true,true\nB,false,true,true\n",
+    )
+    .unwrap();
+    let validate = |questions: &str| {
+        std::fs::write(
+            &question_file,
+            format!("player1,player1_pos,player2,player2_pos,skill_factor\n{questions}"),
+        )
+        .unwrap();
+        let args = player_rank_cli::Cli::try_parse_from([
+            "player_rank_cli",
+            "validate",
+            player_file.to_str().unwrap(),
+            question_file.to_str().unwrap(),
+        ])
+        .unwrap();
+        player_rank_cli::run(args)
+    };
+    assert!(validate("A,Atk,B,Atk,2\nA,Atk,B,Atk,2\nA,Atk,C,Atk,2\n").is_ok());
+    assert!(validate("A,Atk,B,Atk,2\nA,Atk,B,Atk,-1\n").is_err());
+    assert!(validate("A,Atk,A,Atk,2\n").is_err());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn library_errors() {
+    // Too few players to rank is an error instead of a panic
+    assert_eq!(
+        PlayerRank::new(common::test_players(3), Questions::new(), Some(0)).err(),
+        Some(Error::TooFewPlayers {
+            needed: 4,
+            found: 3
+        })
+    );
+
+    let mut player_rank =
+        PlayerRank::new(common::test_players(4), Questions::new(), Some(0)).unwrap();
+    assert_eq!(player_rank.give_response(1.0), Err(Error::NoActiveQuestion));
+    assert_eq!(player_rank.next_section(), Err(Error::MinSetNotReached));
+    assert!(player_rank.get_next_question().0.is_some());
+    assert_eq!(
+        player_rank.give_response(-1.0),
+        Err(Error::InvalidResponse(-1.0))
+    );
+    assert!(player_rank.give_response(1.0).is_ok());
+
+    // Every error can be shown to a user
+    let mut players = common::test_players(2);
+    players.players[1].id = String::from("Alice");
+    let err = players.check_references().unwrap_err();
+    assert_eq!(err, Error::DuplicateId(String::from("Alice")));
+    assert_eq!(err.to_string(), "More than one player has the id `Alice`");
+    assert_eq!(
+        players.get("Zed").err(),
+        Some(Error::UnknownPlayer(String::from("Zed")))
+    );
+}